@@ -6,7 +6,7 @@ fn main() {
     let msg = "1".as_bytes().to_vec();
 
     let sig = hex::decode("c46eee1875fd3a2ac7f4877080e17ecea2ab66f51bdaa1581acf92ca65323f5f415314242d5513c070ef7fbd78593c0a9116fdeb6288ff28d67a503f7e23bf84").unwrap();
-    let out = self::verify(&addr, &msg, &sig);
+    let out = self::verify(addr, &msg, &sig);
     println!("{:?}", out);
 }
 
@@ -0,0 +1,27 @@
+// 本地OTLP/HTTP collector桩，用于检查trace导出
+// 运行: cargo run --example otlp_collector
+// 服务配置: [log] otlp_endpoint = "http://127.0.0.1:4318/v1/traces"
+use axum::body::Bytes;
+use axum::http::HeaderMap;
+use axum::routing::post;
+use axum::Router;
+use std::net::SocketAddr;
+
+#[tokio::main]
+async fn main() {
+    let app = Router::new().route("/v1/traces", post(traces));
+    let addr = SocketAddr::from(([127, 0, 0, 1], 4318));
+    println!("OTLP collector listening on {}", addr);
+    axum::Server::bind(&addr).serve(app.into_make_service()).await.unwrap();
+}
+
+async fn traces(headers: HeaderMap, body: Bytes) {
+    let content_type = headers.get("content-type").and_then(|v| v.to_str().ok()).unwrap_or("");
+    println!("Received {} bytes of {} traces", body.len(), content_type);
+    // span名与属性以明文出现在protobuf中，这里只打印出可读的部分
+    let text: String = body
+        .iter()
+        .map(|&b| if b.is_ascii_graphic() { b as char } else { ' ' })
+        .collect();
+    println!("{}", text.split_whitespace().collect::<Vec<_>>().join(" "));
+}
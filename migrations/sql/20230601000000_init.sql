@@ -0,0 +1,52 @@
+-- Portable between SQLite and PostgreSQL. Timestamps are unix milliseconds and
+-- booleans are stored as 0/1, since the sqlx Any driver can't decode SQLite BOOLEAN.
+
+CREATE TABLE nonce (
+    user_id TEXT PRIMARY KEY,
+    nonce BIGINT NOT NULL
+);
+
+CREATE TABLE device (
+    device_id TEXT PRIMARY KEY,
+    device_name TEXT NOT NULL,
+    mac TEXT NOT NULL,
+    fingerprint TEXT NOT NULL DEFAULT '',
+    online BIGINT NOT NULL,
+    add_time BIGINT NOT NULL,
+    update_time BIGINT NOT NULL,
+    -- JSON array of strings
+    tags TEXT NOT NULL DEFAULT '[]'
+);
+
+CREATE INDEX device_mac ON device (mac, fingerprint);
+
+CREATE TABLE binding (
+    user_id TEXT NOT NULL,
+    device_id TEXT NOT NULL,
+    device_name TEXT NOT NULL,
+    PRIMARY KEY (user_id, device_id)
+);
+
+CREATE INDEX binding_device ON binding (device_id);
+
+CREATE TABLE device_group (
+    group_id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    name TEXT NOT NULL
+);
+
+CREATE INDEX device_group_user ON device_group (user_id);
+
+CREATE TABLE group_device (
+    group_id TEXT NOT NULL,
+    device_id TEXT NOT NULL,
+    PRIMARY KEY (group_id, device_id)
+);
+
+CREATE TABLE group_share (
+    group_id TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    PRIMARY KEY (group_id, user_id)
+);
+
+CREATE INDEX group_share_user ON group_share (user_id);
@@ -0,0 +1,16 @@
+-- Token revocation and bans, managed through the admin API.
+
+CREATE TABLE token_revocation (
+    user_id TEXT PRIMARY KEY,
+    revoked_before BIGINT NOT NULL
+);
+
+-- kind is one of 'user', 'device', 'ip'; expires_at is NULL for permanent bans
+CREATE TABLE ban (
+    kind TEXT NOT NULL,
+    value TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    created_at BIGINT NOT NULL,
+    expires_at BIGINT,
+    PRIMARY KEY (kind, value)
+);
@@ -0,0 +1,15 @@
+-- Append-only audit log; old entries are pruned according to [audit] retention_days.
+
+CREATE TABLE audit (
+    action TEXT NOT NULL,
+    actor TEXT NOT NULL,
+    target TEXT NOT NULL,
+    ip TEXT NOT NULL,
+    user_agent TEXT NOT NULL,
+    detail TEXT NOT NULL,
+    created_at BIGINT NOT NULL
+);
+
+CREATE INDEX audit_created_at ON audit (created_at);
+CREATE INDEX audit_actor ON audit (actor, created_at);
+CREATE INDEX audit_target ON audit (target, created_at);
@@ -0,0 +1,3 @@
+-- Look up the groups containing a device when publishing its presence changes.
+
+CREATE INDEX group_device_device ON group_device (device_id);
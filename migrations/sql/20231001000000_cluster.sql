@@ -0,0 +1,13 @@
+-- Node affinity of authenticated connections, used to route messages across nodes.
+
+CREATE TABLE connection (
+    node_id TEXT NOT NULL,
+    conn_id BIGINT NOT NULL,
+    user_id TEXT NOT NULL,
+    device_id TEXT NOT NULL,
+    addr TEXT NOT NULL,
+    connected_at BIGINT NOT NULL,
+    PRIMARY KEY (node_id, conn_id)
+);
+
+CREATE INDEX connection_user ON connection (user_id);
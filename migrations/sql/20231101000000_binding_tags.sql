@@ -0,0 +1,7 @@
+-- Tags belong to each user's binding instead of the shared device row; existing device
+-- tags are copied to every binding of the device.
+
+ALTER TABLE binding ADD COLUMN tags TEXT NOT NULL DEFAULT '[]';
+
+UPDATE binding SET tags = (SELECT device.tags FROM device WHERE device.device_id = binding.device_id)
+WHERE EXISTS (SELECT 1 FROM device WHERE device.device_id = binding.device_id);
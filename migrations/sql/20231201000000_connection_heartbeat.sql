@@ -0,0 +1,8 @@
+-- Nodes refresh heartbeat_at of their connection records; records of nodes that stopped
+-- refreshing them are removed by the other nodes.
+
+ALTER TABLE connection ADD COLUMN heartbeat_at BIGINT NOT NULL DEFAULT 0;
+
+UPDATE connection SET heartbeat_at = connected_at;
+
+CREATE INDEX connection_heartbeat ON connection (heartbeat_at);
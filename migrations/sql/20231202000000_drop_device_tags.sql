@@ -0,0 +1,3 @@
+-- Tags are kept per binding since 20231101000000; the device column is no longer used.
+
+ALTER TABLE device DROP COLUMN tags;
@@ -0,0 +1,87 @@
+use std::sync::Mutex;
+
+use anyhow::{anyhow, Error, Result};
+use axum::async_trait;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tracing::Level;
+
+use super::{BusMessage, MessageBus};
+
+// 单节点部署使用，消息直接交给本节点
+pub struct LocalBus {
+    node_id: String,
+    tx: UnboundedSender<BusMessage>,
+    rx: Mutex<Option<UnboundedReceiver<BusMessage>>>,
+}
+
+impl LocalBus {
+    pub fn new(node_id: &str) -> Self {
+        let (tx, rx) = mpsc::unbounded_channel();
+        LocalBus { node_id: node_id.to_owned(), tx, rx: Mutex::new(Some(rx)) }
+    }
+}
+
+#[async_trait]
+impl MessageBus for LocalBus {
+    async fn broadcast(&self, msg: &BusMessage) -> Result<(), Error> {
+        self.tx.send(msg.clone()).map_err(|_| anyhow!("Bus is closed"))
+    }
+
+    // 连接记录中其他节点的id来自之前的运行，这些节点已不存在，消息丢弃
+    async fn send(&self, node_id: &str, msg: &BusMessage) -> Result<(), Error> {
+        if node_id != self.node_id {
+            tracing::event!(Level::WARN, "Dropped bus message for unknown node {}", node_id);
+            return Ok(());
+        }
+        self.broadcast(msg).await
+    }
+
+    fn subscribe(&self) -> Result<UnboundedReceiver<BusMessage>, Error> {
+        self.rx
+            .lock()
+            .unwrap()
+            .take()
+            .ok_or_else(|| anyhow!("Bus is already subscribed"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close_user(user_id: &str) -> BusMessage {
+        BusMessage::CloseUser { user_id: user_id.to_owned(), code: 4000, reason: String::new() }
+    }
+
+    fn user_id(msg: BusMessage) -> String {
+        match msg {
+            BusMessage::CloseUser { user_id, .. } => user_id,
+            msg => panic!("unexpected {:?}", msg),
+        }
+    }
+
+    #[tokio::test]
+    async fn broadcast_reaches_this_node() {
+        let bus = LocalBus::new("a");
+        let mut rx = bus.subscribe().unwrap();
+        bus.broadcast(&close_user("alice")).await.unwrap();
+        assert_eq!(user_id(rx.try_recv().unwrap()), "alice");
+    }
+
+    #[tokio::test]
+    async fn send_only_reaches_this_node() {
+        let bus = LocalBus::new("a");
+        let mut rx = bus.subscribe().unwrap();
+        bus.send("b", &close_user("bob")).await.unwrap();
+        bus.send("a", &close_user("alice")).await.unwrap();
+        assert_eq!(user_id(rx.try_recv().unwrap()), "alice");
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn subscribes_once() {
+        let bus = LocalBus::new("a");
+        bus.subscribe().unwrap();
+        assert!(bus.subscribe().is_err());
+    }
+}
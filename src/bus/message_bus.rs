@@ -0,0 +1,71 @@
+use std::ops::Deref;
+use std::sync::Arc;
+
+use anyhow::{Error, Result};
+use axum::async_trait;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::mpsc::UnboundedReceiver;
+
+use super::{LocalBus, RedisBus};
+use crate::config::{BusConfig, BusKind};
+use crate::types::PresenceEvent;
+
+// 节点之间转发的消息
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BusMessage {
+    // 设备状态变化，推送给订阅了且在viewers中的用户的连接
+    Presence { viewers: Vec<String>, event: PresenceEvent },
+    // 关闭该用户在收到消息的节点上的连接
+    CloseUser { user_id: String, code: u16, reason: String },
+    // 推送给该用户在收到消息的节点上的连接，只发给有该用户连接的节点
+    Deliver { user_id: String, method: String, params: Value },
+}
+
+// 节点之间的消息总线，本进程内与redis各有一个实现
+// 发出的消息本节点也会收到，消息只在接收端处理
+#[async_trait]
+pub trait MessageBus: Send + Sync {
+    // 发送给所有节点
+    async fn broadcast(&self, msg: &BusMessage) -> Result<(), Error>;
+    // 发送给指定节点
+    async fn send(&self, node_id: &str, msg: &BusMessage) -> Result<(), Error>;
+    // 本节点收到的消息，只能调用一次
+    fn subscribe(&self) -> Result<UnboundedReceiver<BusMessage>, Error>;
+}
+
+// 各handler共享的消息总线句柄，按配置选择实现
+#[derive(Clone)]
+pub struct Bus {
+    node_id: Arc<str>,
+    inner: Arc<dyn MessageBus>,
+}
+
+impl Deref for Bus {
+    type Target = dyn MessageBus;
+
+    fn deref(&self) -> &Self::Target {
+        self.inner.as_ref()
+    }
+}
+
+impl Bus {
+    pub async fn new(config: &BusConfig) -> Result<Self> {
+        let node_id = config.node_id.clone().unwrap_or_else(random_node_id);
+        let inner: Arc<dyn MessageBus> = match config.kind {
+            BusKind::Local => Arc::new(LocalBus::new(&node_id)),
+            BusKind::Redis => Arc::new(RedisBus::new(config, &node_id).await?),
+        };
+        Ok(Bus { node_id: node_id.into(), inner })
+    }
+
+    pub fn node_id(&self) -> &str {
+        &self.node_id
+    }
+}
+
+fn random_node_id() -> String {
+    format!("{:08x}", rand::thread_rng().gen::<u32>())
+}
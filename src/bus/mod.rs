@@ -0,0 +1,7 @@
+pub use local::*;
+pub use message_bus::*;
+pub use redis_bus::*;
+
+mod local;
+mod message_bus;
+mod redis_bus;
@@ -0,0 +1,161 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, Error, Result};
+use axum::async_trait;
+use futures::StreamExt;
+use redis::aio::MultiplexedConnection;
+use redis::Client;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::sync::Mutex;
+use tracing::Level;
+
+use super::{BusMessage, MessageBus};
+use crate::config::BusConfig;
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+// 通过redis pub/sub在节点之间转发，消息为JSON
+// 所有节点订阅 <channel>:bus，每个节点另外订阅 <channel>:node:<node_id>
+// 订阅断开期间的消息会丢失
+pub struct RedisBus {
+    client: Client,
+    broadcast_channel: String,
+    node_prefix: String,
+    node_channel: String,
+    // 发布用的连接，出错后重新建立
+    conn: Mutex<Option<MultiplexedConnection>>,
+}
+
+impl RedisBus {
+    // 启动时连接一次，redis不可用时启动失败
+    pub async fn new(config: &BusConfig, node_id: &str) -> Result<Self> {
+        let client = Client::open(config.redis_url.as_str())?;
+        let conn = client.get_multiplexed_tokio_connection().await?;
+        let node_prefix = format!("{}:node:", config.channel);
+        Ok(RedisBus {
+            client,
+            broadcast_channel: format!("{}:bus", config.channel),
+            node_channel: format!("{}{}", node_prefix, node_id),
+            node_prefix,
+            conn: Mutex::new(Some(conn)),
+        })
+    }
+
+    async fn publish(&self, channel: &str, msg: &BusMessage) -> Result<(), Error> {
+        let payload = serde_json::to_string(msg)?;
+        let mut conn = self.conn.lock().await;
+        let publisher = match conn.as_mut() {
+            Some(publisher) => publisher,
+            None => conn.insert(self.client.get_multiplexed_tokio_connection().await?),
+        };
+        let result = redis::cmd("PUBLISH")
+            .arg(channel)
+            .arg(payload)
+            .query_async::<_, i64>(publisher)
+            .await;
+        if let Err(e) = result {
+            *conn = None;
+            return Err(anyhow!(e));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl MessageBus for RedisBus {
+    async fn broadcast(&self, msg: &BusMessage) -> Result<(), Error> {
+        self.publish(&self.broadcast_channel, msg).await
+    }
+
+    async fn send(&self, node_id: &str, msg: &BusMessage) -> Result<(), Error> {
+        self.publish(&format!("{}{}", self.node_prefix, node_id), msg).await
+    }
+
+    fn subscribe(&self) -> Result<UnboundedReceiver<BusMessage>, Error> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let channels = vec![self.broadcast_channel.clone(), self.node_channel.clone()];
+        tokio::spawn(listen(self.client.clone(), channels, tx));
+        Ok(rx)
+    }
+}
+
+// 订阅断开后重连，直到接收端关闭
+async fn listen(client: Client, channels: Vec<String>, tx: UnboundedSender<BusMessage>) {
+    loop {
+        match receive(&client, &channels, &tx).await {
+            Ok(()) => return,
+            Err(e) => {
+                tracing::event!(Level::ERROR, "Redis subscription failed: {:?}", e);
+                tokio::time::sleep(RECONNECT_DELAY).await;
+            }
+        }
+    }
+}
+
+async fn receive(
+    client: &Client,
+    channels: &[String],
+    tx: &UnboundedSender<BusMessage>,
+) -> Result<(), Error> {
+    let mut pubsub = client.get_async_connection().await?.into_pubsub();
+    for channel in channels {
+        pubsub.subscribe(channel).await?;
+    }
+    tracing::event!(Level::INFO, "Subscribed to redis channels {:?}", channels);
+    let mut messages = pubsub.on_message();
+    while let Some(message) = messages.next().await {
+        let payload: String = message.get_payload()?;
+        match serde_json::from_str::<BusMessage>(&payload) {
+            Ok(msg) => {
+                if tx.send(msg).is_err() {
+                    return Ok(());
+                }
+            }
+            Err(e) => tracing::event!(Level::WARN, "Ignored invalid bus message: {}", e),
+        }
+    }
+    Err(anyhow!("Redis subscription closed"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn recv(rx: &mut UnboundedReceiver<BusMessage>) -> String {
+        match tokio::time::timeout(Duration::from_secs(5), rx.recv()).await {
+            Ok(Some(BusMessage::CloseUser { user_id, .. })) => user_id,
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    // 需要可用的redis：REDIS_URL=redis://127.0.0.1:6379/ cargo test -- --ignored
+    #[tokio::test]
+    #[ignore]
+    async fn round_trip() {
+        let config = BusConfig {
+            redis_url: std::env::var("REDIS_URL").unwrap_or("redis://127.0.0.1:6379/".to_owned()),
+            channel: format!("deeplink-test-{}", std::process::id()),
+            ..Default::default()
+        };
+        let a = RedisBus::new(&config, "a").await.unwrap();
+        let b = RedisBus::new(&config, "b").await.unwrap();
+        let mut rx_a = a.subscribe().unwrap();
+        let mut rx_b = b.subscribe().unwrap();
+        // 订阅建立前发布的消息会丢失
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        let msg = |user_id: &str| BusMessage::CloseUser {
+            user_id: user_id.to_owned(),
+            code: 4000,
+            reason: String::new(),
+        };
+        a.broadcast(&msg("alice")).await.unwrap();
+        assert_eq!(recv(&mut rx_a).await, "alice");
+        assert_eq!(recv(&mut rx_b).await, "alice");
+
+        a.send("b", &msg("bob")).await.unwrap();
+        assert_eq!(recv(&mut rx_b).await, "bob");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(rx_a.try_recv().is_err());
+    }
+}
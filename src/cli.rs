@@ -0,0 +1,68 @@
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+
+pub const USAGE: &str = "\
+Usage: deeplink-rs [--config <path>] [command]
+
+Commands:
+    serve                 Start the server (default)
+    migrate [--dry-run]   Run pending data migrations and exit
+    schema [--check <path>]
+                          Print the OpenRPC document of the protocol, or check that
+                          the document at <path> is up to date
+";
+
+#[derive(Debug)]
+pub enum Command {
+    Serve,
+    Migrate { dry_run: bool },
+    Schema { check: Option<PathBuf> },
+}
+
+#[derive(Debug)]
+pub struct Cli {
+    pub config: Option<PathBuf>,
+    pub command: Command,
+}
+
+impl Cli {
+    pub fn parse() -> Result<Self> {
+        Self::parse_from(std::env::args().skip(1))
+    }
+
+    fn parse_from(args: impl Iterator<Item = String>) -> Result<Self> {
+        let mut config = std::env::var_os("DEEPLINK_CONFIG").map(PathBuf::from);
+        let mut command = None;
+        let mut dry_run = false;
+        let mut check = None;
+
+        let mut args = args;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--config" | "-c" => {
+                    let path = args.next().ok_or_else(|| anyhow!("--config requires a path"))?;
+                    config = Some(PathBuf::from(path));
+                }
+                "--dry-run" => dry_run = true,
+                "--check" => {
+                    let path = args.next().ok_or_else(|| anyhow!("--check requires a path"))?;
+                    check = Some(PathBuf::from(path));
+                }
+                "serve" | "migrate" | "schema" if command.is_none() => command = Some(arg),
+                _ => return Err(anyhow!("Unexpected argument: {}", arg)),
+            }
+        }
+
+        if check.is_some() && command.as_deref() != Some("schema") {
+            return Err(anyhow!("--check is only valid for schema"));
+        }
+        let command = match command.as_deref() {
+            None | Some("serve") if !dry_run => Command::Serve,
+            Some("migrate") => Command::Migrate { dry_run },
+            Some("schema") if !dry_run => Command::Schema { check },
+            _ => return Err(anyhow!("--dry-run is only valid for migrate")),
+        };
+        Ok(Cli { config, command })
+    }
+}
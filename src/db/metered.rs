@@ -0,0 +1,259 @@
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Instant;
+
+use anyhow::{Error, Result};
+use axum::async_trait;
+
+use super::Storage;
+use crate::metrics::STORAGE_DURATION;
+use crate::types::{
+    AuditEntry, AuditFilter, Ban, BanKind, ConnectionRecord, DeviceBinding, DeviceGroup,
+    DeviceInfo, Timestamp,
+};
+
+// 包装一个存储后端，记录每次操作的耗时与结果
+pub struct MeteredStorage {
+    backend: &'static str,
+    inner: Arc<dyn Storage>,
+}
+
+impl MeteredStorage {
+    pub fn new(backend: &'static str, inner: Arc<dyn Storage>) -> Self {
+        MeteredStorage { backend, inner }
+    }
+
+    async fn observe<T>(
+        &self,
+        operation: &str,
+        fut: impl Future<Output = Result<T, Error>>,
+    ) -> Result<T, Error> {
+        let start = Instant::now();
+        let result = fut.await;
+        let outcome = if result.is_ok() { "ok" } else { "error" };
+        STORAGE_DURATION
+            .with_label_values(&[self.backend, operation, outcome])
+            .observe(start.elapsed().as_secs_f64());
+        result
+    }
+}
+
+#[async_trait]
+impl Storage for MeteredStorage {
+    async fn ping(&self) -> Result<(), Error> {
+        self.observe("ping", self.inner.ping()).await
+    }
+
+    async fn update_nonce(&self, user_id: &str, nonce: u64) -> Result<(), Error> {
+        self.observe("update_nonce", self.inner.update_nonce(user_id, nonce)).await
+    }
+
+    async fn get_nonce(&self, user_id: &str) -> Result<u64, Error> {
+        self.observe("get_nonce", self.inner.get_nonce(user_id)).await
+    }
+
+    async fn update_device(&self, device_info: DeviceInfo) -> Result<(), Error> {
+        self.observe("update_device", self.inner.update_device(device_info)).await
+    }
+
+    async fn device_id_exist(&self, device_id: &str) -> Result<bool, Error> {
+        self.observe("device_id_exist", self.inner.device_id_exist(device_id)).await
+    }
+
+    async fn get_devices(&self, device_ids: &[String]) -> Result<Vec<DeviceInfo>, Error> {
+        self.observe("get_devices", self.inner.get_devices(device_ids)).await
+    }
+
+    async fn find_device(
+        &self,
+        device_ids: &[String],
+        mac: &str,
+        fingerprint: &str,
+    ) -> Result<Option<DeviceInfo>, Error> {
+        self.observe("find_device", self.inner.find_device(device_ids, mac, fingerprint))
+            .await
+    }
+
+    async fn set_device_online(&self, device_id: &str, online: bool) -> Result<(), Error> {
+        self.observe("set_device_online", self.inner.set_device_online(device_id, online))
+            .await
+    }
+
+    async fn bind_device(&self, binding: &DeviceBinding) -> Result<(), Error> {
+        self.observe("bind_device", self.inner.bind_device(binding)).await
+    }
+
+    async fn set_binding_tags(
+        &self,
+        user_id: &str,
+        device_id: &str,
+        tags: &[String],
+    ) -> Result<(), Error> {
+        self.observe("set_binding_tags", self.inner.set_binding_tags(user_id, device_id, tags))
+            .await
+    }
+
+    async fn unbind_device(&self, user_id: &str, device_id: &str) -> Result<bool, Error> {
+        self.observe("unbind_device", self.inner.unbind_device(user_id, device_id))
+            .await
+    }
+
+    async fn get_bindings(&self, user_id: &str) -> Result<Vec<DeviceBinding>, Error> {
+        self.observe("get_bindings", self.inner.get_bindings(user_id)).await
+    }
+
+    async fn is_bound(&self, user_id: &str, device_id: &str) -> Result<bool, Error> {
+        self.observe("is_bound", self.inner.is_bound(user_id, device_id)).await
+    }
+
+    async fn get_device_viewers(&self, device_id: &str) -> Result<Vec<String>, Error> {
+        self.observe("get_device_viewers", self.inner.get_device_viewers(device_id))
+            .await
+    }
+
+    async fn insert_group(&self, group: &DeviceGroup) -> Result<(), Error> {
+        self.observe("insert_group", self.inner.insert_group(group)).await
+    }
+
+    async fn get_group(&self, group_id: &str) -> Result<Option<DeviceGroup>, Error> {
+        self.observe("get_group", self.inner.get_group(group_id)).await
+    }
+
+    async fn get_groups(&self, user_id: &str) -> Result<Vec<DeviceGroup>, Error> {
+        self.observe("get_groups", self.inner.get_groups(user_id)).await
+    }
+
+    async fn get_shared_groups(&self, user_id: &str) -> Result<Vec<DeviceGroup>, Error> {
+        self.observe("get_shared_groups", self.inner.get_shared_groups(user_id)).await
+    }
+
+    async fn rename_group(&self, group_id: &str, name: &str) -> Result<Option<DeviceGroup>, Error> {
+        self.observe("rename_group", self.inner.rename_group(group_id, name)).await
+    }
+
+    async fn add_group_devices(
+        &self,
+        group_id: &str,
+        device_ids: &[String],
+    ) -> Result<Option<DeviceGroup>, Error> {
+        self.observe("add_group_devices", self.inner.add_group_devices(group_id, device_ids))
+            .await
+    }
+
+    async fn remove_group_devices(
+        &self,
+        group_id: &str,
+        device_ids: &[String],
+    ) -> Result<Option<DeviceGroup>, Error> {
+        self.observe("remove_group_devices", self.inner.remove_group_devices(group_id, device_ids))
+            .await
+    }
+
+    async fn share_group(
+        &self,
+        group_id: &str,
+        user_id: &str,
+    ) -> Result<Option<DeviceGroup>, Error> {
+        self.observe("share_group", self.inner.share_group(group_id, user_id)).await
+    }
+
+    async fn unshare_group(
+        &self,
+        group_id: &str,
+        user_id: &str,
+    ) -> Result<Option<DeviceGroup>, Error> {
+        self.observe("unshare_group", self.inner.unshare_group(group_id, user_id)).await
+    }
+
+    async fn delete_group(&self, group_id: &str) -> Result<(), Error> {
+        self.observe("delete_group", self.inner.delete_group(group_id)).await
+    }
+
+    async fn search_users(
+        &self,
+        query: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<String>, Error> {
+        self.observe("search_users", self.inner.search_users(query, limit, offset))
+            .await
+    }
+
+    async fn search_devices(
+        &self,
+        query: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DeviceInfo>, Error> {
+        self.observe("search_devices", self.inner.search_devices(query, limit, offset))
+            .await
+    }
+
+    async fn revoke_tokens(&self, user_id: &str, before: Timestamp) -> Result<(), Error> {
+        self.observe("revoke_tokens", self.inner.revoke_tokens(user_id, before)).await
+    }
+
+    async fn tokens_revoked_before(&self, user_id: &str) -> Result<Option<Timestamp>, Error> {
+        self.observe("tokens_revoked_before", self.inner.tokens_revoked_before(user_id))
+            .await
+    }
+
+    async fn upsert_ban(&self, ban: &Ban) -> Result<(), Error> {
+        self.observe("upsert_ban", self.inner.upsert_ban(ban)).await
+    }
+
+    async fn delete_ban(&self, kind: BanKind, value: &str) -> Result<bool, Error> {
+        self.observe("delete_ban", self.inner.delete_ban(kind, value)).await
+    }
+
+    async fn get_bans(&self) -> Result<Vec<Ban>, Error> {
+        self.observe("get_bans", self.inner.get_bans()).await
+    }
+
+    async fn upsert_connection(&self, record: &ConnectionRecord) -> Result<(), Error> {
+        self.observe("upsert_connection", self.inner.upsert_connection(record)).await
+    }
+
+    async fn delete_connection(&self, node_id: &str, conn_id: u64) -> Result<(), Error> {
+        self.observe("delete_connection", self.inner.delete_connection(node_id, conn_id))
+            .await
+    }
+
+    async fn delete_node_connections(&self, node_id: &str) -> Result<u64, Error> {
+        self.observe("delete_node_connections", self.inner.delete_node_connections(node_id))
+            .await
+    }
+
+    async fn touch_node_connections(&self, node_id: &str, at: Timestamp) -> Result<(), Error> {
+        self.observe("touch_node_connections", self.inner.touch_node_connections(node_id, at))
+            .await
+    }
+
+    async fn delete_stale_connections(&self, before: Timestamp) -> Result<u64, Error> {
+        self.observe("delete_stale_connections", self.inner.delete_stale_connections(before))
+            .await
+    }
+
+    async fn get_user_connections(&self, user_id: &str) -> Result<Vec<ConnectionRecord>, Error> {
+        self.observe("get_user_connections", self.inner.get_user_connections(user_id))
+            .await
+    }
+
+    async fn insert_audit(&self, entry: &AuditEntry) -> Result<(), Error> {
+        self.observe("insert_audit", self.inner.insert_audit(entry)).await
+    }
+
+    async fn get_audit(
+        &self,
+        filter: &AuditFilter,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<AuditEntry>, Error> {
+        self.observe("get_audit", self.inner.get_audit(filter, limit, offset)).await
+    }
+
+    async fn delete_audit_before(&self, before: Timestamp) -> Result<u64, Error> {
+        self.observe("delete_audit_before", self.inner.delete_audit_before(before))
+            .await
+    }
+}
@@ -0,0 +1,397 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, Document},
+    error::{ErrorKind, WriteFailure},
+    Database,
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use tracing::Level;
+
+use crate::types::Timestamp;
+
+// 数据迁移锁的租期，超过后认为持有锁的实例已异常退出，其他实例可以接管
+const LOCK_LEASE: Duration = Duration::from_secs(10 * 60);
+const LOCK_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+// 迁移步骤以数据描述，checksum由步骤内容计算，已执行的迁移被修改后可以被发现
+// 每个步骤都需要是幂等的：filter只匹配尚未迁移的文档
+pub enum Step {
+    UpdateMany { collection: &'static str, filter: Document, update: Vec<Document> },
+    // 需要关联其他集合时，对filter匹配的文档执行聚合，pipeline以$merge写回
+    Aggregate { collection: &'static str, filter: Document, pipeline: Vec<Document> },
+}
+
+pub struct Migration {
+    pub id: &'static str,
+    pub description: &'static str,
+    pub steps: Vec<Step>,
+}
+
+impl Migration {
+    pub fn checksum(&self) -> String {
+        let mut bytes = self.id.as_bytes().to_vec();
+        for step in &self.steps {
+            match step {
+                Step::UpdateMany { collection, filter, update: stages }
+                | Step::Aggregate { collection, filter, pipeline: stages } => {
+                    bytes.extend_from_slice(collection.as_bytes());
+                    bytes.extend(bson::to_vec(filter).unwrap_or_default());
+                    for stage in stages {
+                        bytes.extend(bson::to_vec(stage).unwrap_or_default());
+                    }
+                }
+            }
+        }
+        hex::encode(sp_core::blake2_256(&bytes))
+    }
+}
+
+// 所有迁移，按id顺序执行；新增迁移追加到末尾，已发布的迁移不要修改
+pub fn migrations() -> Vec<Migration> {
+    vec![
+        Migration {
+            id: "0001_device_timestamps",
+            // 早期版本的utils::now()写入的add_time/update_time是形如"0.000000100"的字符串，
+            // 无法还原真实时间，这里改用文档_id（ObjectId）中的创建时间，并存为BSON datetime
+            description: "Store device add_time/update_time as BSON datetime",
+            steps: ["add_time", "update_time"]
+                .into_iter()
+                .map(|field| Step::UpdateMany {
+                    collection: "device",
+                    filter: doc! { field: {"$not": {"$type": "date"}} },
+                    update: vec![doc! {"$set": { field: {"$toDate": "$_id"} }}],
+                })
+                .collect(),
+        },
+        Migration {
+            id: "0002_binding_tags",
+            // 标签从设备文档移到各用户的绑定中，原有标签复制到该设备的每个绑定
+            description: "Copy device tags to each binding of the device",
+            steps: vec![Step::Aggregate {
+                collection: "binding",
+                filter: doc! {"tags": {"$exists": false}},
+                pipeline: vec![
+                    doc! {"$lookup": {
+                        "from": "device",
+                        "localField": "device_id",
+                        "foreignField": "device_id",
+                        "as": "device",
+                    }},
+                    doc! {"$project": {
+                        "tags": {"$ifNull": [{"$arrayElemAt": ["$device.tags", 0]}, []]},
+                    }},
+                    doc! {"$merge": {
+                        "into": "binding",
+                        "on": "_id",
+                        "whenMatched": "merge",
+                        "whenNotMatched": "discard",
+                    }},
+                ],
+            }],
+        },
+        Migration {
+            id: "0003_normalize_mac",
+            // 早期版本原样保存注册时的mac，重新注册时按规范化后的mac查找不到原设备
+            description: "Normalize device mac to 00:2B:67:6F:74:72",
+            steps: vec![Step::UpdateMany {
+                collection: "device",
+                filter: doc! {"$and": [
+                    {"mac": {"$regex": NORMALIZABLE_MAC}},
+                    {"mac": {"$not": {"$regex": NORMALIZED_MAC}}},
+                ]},
+                update: vec![doc! {"$set": {"mac": normalized_mac()}}],
+            }],
+        },
+        Migration {
+            id: "0004_drop_device_tags",
+            // 标签已迁移到各用户的绑定上（0002_binding_tags）
+            description: "Remove tags from device",
+            steps: vec![Step::UpdateMany {
+                collection: "device",
+                filter: doc! {"tags": {"$exists": true}},
+                update: vec![doc! {"$unset": "tags"}],
+            }],
+        },
+    ]
+}
+
+// 与utils::normalize_mac接受的写法一致
+const NORMALIZABLE_MAC: &str = "^\\s*([0-9A-Fa-f]{2}([:-]?)[0-9A-Fa-f]{2}(\\2[0-9A-Fa-f]{2}){4}\
+                                |[0-9A-Fa-f]{4}\\.[0-9A-Fa-f]{4}\\.[0-9A-Fa-f]{4})\\s*$";
+const NORMALIZED_MAC: &str = "^([0-9A-F]{2}:){5}[0-9A-F]{2}$";
+
+// 去掉分隔符与空白并转为大写后，每两位之间插入 ":"
+fn normalized_mac() -> Document {
+    let mut digits = bson::Bson::from(doc! {"$trim": {"input": "$mac"}});
+    for sep in [":", "-", "."] {
+        digits = doc! {"$replaceAll": {"input": digits, "find": sep, "replacement": ""}}.into();
+    }
+    let digits = doc! {"$toUpper": digits};
+    let mut parts = vec![];
+    for i in 0..6 {
+        if i > 0 {
+            parts.push(bson::Bson::from(":"));
+        }
+        parts.push(doc! {"$substrCP": ["$$digits", i * 2, 2]}.into());
+    }
+    doc! {"$let": {"vars": {"digits": digits}, "in": {"$concat": parts}}}
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct AppliedMigration {
+    #[serde(rename = "_id")]
+    id: String,
+    checksum: String,
+    modified: u64,
+    applied_at: Timestamp,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct MigrationLock {
+    #[serde(rename = "_id")]
+    id: String,
+    owner: String,
+    locked_at: Timestamp,
+}
+
+#[derive(Debug)]
+pub enum Outcome {
+    // 已执行过
+    Skipped,
+    Applied { modified: u64 },
+    // dry run：将被修改的文档数
+    Pending { matched: u64 },
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Skipped => write!(f, "already applied"),
+            Outcome::Applied { modified } => write!(f, "applied, {} documents modified", modified),
+            Outcome::Pending { matched } => write!(f, "pending, {} documents to modify", matched),
+        }
+    }
+}
+
+// 执行所有未执行的迁移；dry_run时只统计将被修改的文档数
+pub async fn run(db: &Database, dry_run: bool) -> Result<Vec<(&'static str, Outcome)>> {
+    let owner = format!("{:016x}", StdRng::from_entropy().gen::<u64>());
+    let mut outcomes = vec![];
+    for migration in migrations() {
+        let outcome = if dry_run {
+            dry_run_migration(db, &migration).await?
+        } else {
+            apply_migration(db, &migration, &owner).await?
+        };
+        tracing::event!(
+            Level::INFO,
+            "Migration {} ({}): {}",
+            migration.id,
+            migration.description,
+            outcome
+        );
+        outcomes.push((migration.id, outcome));
+    }
+    Ok(outcomes)
+}
+
+// 返回尚未执行的迁移id
+pub async fn pending(db: &Database) -> Result<Vec<&'static str>> {
+    let mut pending = vec![];
+    for migration in migrations() {
+        if applied(db, &migration).await?.is_none() {
+            pending.push(migration.id);
+        }
+    }
+    Ok(pending)
+}
+
+// 已执行时返回记录，已执行但checksum不一致时返回错误
+async fn applied(db: &Database, migration: &Migration) -> Result<Option<AppliedMigration>> {
+    let collection = db.collection::<AppliedMigration>("migrations");
+    let record = collection.find_one(doc! {"_id": migration.id}, None).await?;
+    if let Some(record) = &record {
+        if record.checksum != migration.checksum() {
+            return Err(anyhow!(
+                "Migration {} was modified after it was applied (checksum {} != {})",
+                migration.id,
+                record.checksum,
+                migration.checksum()
+            ));
+        }
+    }
+    Ok(record)
+}
+
+async fn dry_run_migration(db: &Database, migration: &Migration) -> Result<Outcome> {
+    if applied(db, migration).await?.is_some() {
+        return Ok(Outcome::Skipped);
+    }
+    let mut matched = 0;
+    for step in &migration.steps {
+        match step {
+            Step::UpdateMany { collection, filter, .. }
+            | Step::Aggregate { collection, filter, .. } => {
+                let collection = db.collection::<Document>(collection);
+                matched += collection.count_documents(filter.clone(), None).await?;
+            }
+        }
+    }
+    Ok(Outcome::Pending { matched })
+}
+
+async fn apply_migration(db: &Database, migration: &Migration, owner: &str) -> Result<Outcome> {
+    if applied(db, migration).await?.is_some() {
+        return Ok(Outcome::Skipped);
+    }
+
+    lock(db, migration.id, owner).await?;
+    // 执行期间定期续租，锁被其他实例接管时中止，避免两个实例同时执行
+    let result = tokio::select! {
+        result = apply_locked(db, migration) => result,
+        e = renew(db, migration.id, owner) => Err(e),
+    };
+    unlock(db, migration.id, owner).await?;
+    result
+}
+
+async fn apply_locked(db: &Database, migration: &Migration) -> Result<Outcome> {
+    // 等待锁期间其他实例可能已经执行完成
+    if applied(db, migration).await?.is_some() {
+        return Ok(Outcome::Skipped);
+    }
+
+    let mut modified = 0;
+    for step in &migration.steps {
+        match step {
+            Step::UpdateMany { collection, filter, update } => {
+                let collection = db.collection::<Document>(collection);
+                let result = collection.update_many(filter.clone(), update.clone(), None).await?;
+                modified += result.modified_count;
+            }
+            Step::Aggregate { collection, filter, pipeline } => {
+                let collection = db.collection::<Document>(collection);
+                modified += collection.count_documents(filter.clone(), None).await?;
+                let mut stages = vec![doc! {"$match": filter.clone()}];
+                stages.extend(pipeline.iter().cloned());
+                // $merge没有输出，遍历游标使聚合执行完成
+                let mut cursor = collection.aggregate(stages, None).await?;
+                while cursor.try_next().await?.is_some() {}
+            }
+        }
+    }
+
+    let collection = db.collection::<AppliedMigration>("migrations");
+    collection
+        .insert_one(
+            AppliedMigration {
+                id: migration.id.to_owned(),
+                checksum: migration.checksum(),
+                modified,
+                applied_at: Timestamp::now(),
+            },
+            None,
+        )
+        .await?;
+    Ok(Outcome::Applied { modified })
+}
+
+// 每个迁移一把锁，以_id唯一性保证同一时间只有一个实例持有
+async fn lock(db: &Database, id: &str, owner: &str) -> Result<()> {
+    let collection = db.collection::<MigrationLock>("migration_lock");
+    loop {
+        let lock = MigrationLock {
+            id: id.to_owned(),
+            owner: owner.to_owned(),
+            locked_at: Timestamp::now(),
+        };
+        match collection.insert_one(&lock, None).await {
+            Ok(_) => return Ok(()),
+            Err(e) if is_duplicate_key(&e) => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        // 接管超过租期的锁
+        let expired = bson::DateTime::from_millis(
+            bson::DateTime::now().timestamp_millis() - LOCK_LEASE.as_millis() as i64,
+        );
+        let takeover = collection
+            .update_one(
+                doc! {"_id": id, "locked_at": {"$lt": expired}},
+                doc! {"$set": {"owner": owner, "locked_at": Timestamp::now()}},
+                None,
+            )
+            .await?;
+        if takeover.modified_count > 0 {
+            tracing::event!(Level::WARN, "Took over expired lock of migration {}", id);
+            return Ok(());
+        }
+
+        tracing::event!(Level::INFO, "Migration {} is locked by another instance, waiting", id);
+        tokio::time::sleep(LOCK_POLL_INTERVAL).await;
+    }
+}
+
+// 每隔1/3租期续租一次，只在锁被接管或租期内未能续租成功时返回
+async fn renew(db: &Database, id: &str, owner: &str) -> anyhow::Error {
+    let collection = db.collection::<MigrationLock>("migration_lock");
+    let mut renewed_at = Instant::now();
+    loop {
+        tokio::time::sleep(LOCK_LEASE / 3).await;
+        let result = collection
+            .update_one(
+                doc! {"_id": id, "owner": owner},
+                doc! {"$set": {"locked_at": Timestamp::now()}},
+                None,
+            )
+            .await;
+        match result {
+            Ok(result) if result.matched_count == 0 => {
+                return anyhow!("Lock of migration {} was taken over by another instance", id)
+            }
+            Ok(_) => renewed_at = Instant::now(),
+            // 下次续租前租期就会过期
+            Err(e) if renewed_at.elapsed() + LOCK_LEASE / 3 >= LOCK_LEASE => {
+                return anyhow!("Renew lock of migration {} failed: {}", id, e)
+            }
+            Err(e) => tracing::event!(Level::WARN, "Renew lock of migration {} failed: {}", id, e),
+        }
+    }
+}
+
+async fn unlock(db: &Database, id: &str, owner: &str) -> Result<()> {
+    let collection = db.collection::<MigrationLock>("migration_lock");
+    collection.delete_one(doc! {"_id": id, "owner": owner}, None).await?;
+    Ok(())
+}
+
+fn is_duplicate_key(e: &mongodb::error::Error) -> bool {
+    matches!(
+        e.kind.as_ref(),
+        ErrorKind::Write(WriteFailure::WriteError(error)) if error.code == 11000
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migration_ids_are_ordered_and_unique() {
+        let ids: Vec<_> = migrations().iter().map(|m| m.id).collect();
+        assert!(ids.windows(2).all(|w| w[0] < w[1]), "{:?}", ids);
+    }
+
+    #[test]
+    fn checksum_depends_on_steps() {
+        let migrations = migrations();
+        let checksums: Vec<_> = migrations.iter().map(|m| m.checksum()).collect();
+        assert_eq!(checksums, migrations.iter().map(|m| m.checksum()).collect::<Vec<_>>());
+        assert!(checksums.windows(2).all(|w| w[0] != w[1]));
+    }
+}
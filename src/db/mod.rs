@@ -1,3 +1,11 @@
+pub use metered::*;
 pub use mongo::*;
+pub use sql::*;
+pub use storage::*;
 
+mod metered;
+mod migration;
 mod mongo;
+mod schema;
+mod sql;
+mod storage;
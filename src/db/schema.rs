@@ -0,0 +1,240 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, Document},
+    options::{IndexOptions, UpdateOptions},
+    Database, IndexModel,
+};
+use serde::{Deserialize, Serialize};
+use tracing::Level;
+
+use super::migration;
+use crate::config::Config;
+use crate::jwt::TOKEN_LIFETIME_SECS;
+
+// 当前程序对应的数据库结构版本，修改索引或文档结构时递增
+// 2: ban、connection、audit的索引及TTL索引
+// 3: connection.heartbeat_at索引
+// 4: device不再保存tags
+pub const SCHEMA_VERSION: u32 = 4;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SchemaVersion {
+    #[serde(rename = "_id")]
+    id: String,
+    version: u32,
+}
+
+struct IndexSpec {
+    collection: &'static str,
+    keys: Document,
+    unique: bool,
+    // TTL索引，文档在该字段时间之后expire_after被删除
+    ttl: bool,
+    expire_after: Option<Duration>,
+}
+
+impl IndexSpec {
+    fn unique(collection: &'static str, keys: Document) -> Self {
+        IndexSpec { collection, keys, unique: true, ttl: false, expire_after: None }
+    }
+
+    fn index(collection: &'static str, keys: Document) -> Self {
+        IndexSpec { collection, keys, unique: false, ttl: false, expire_after: None }
+    }
+
+    // expire_after为None时不过期，已存在的TTL索引会被删除
+    fn ttl(collection: &'static str, field: &str, expire_after: Option<Duration>) -> Self {
+        IndexSpec { collection, keys: doc! {field: 1}, unique: false, ttl: true, expire_after }
+    }
+}
+
+// nonce记录用户已使用的最大nonce，删除后旧的签名可被重放，因此不设置TTL
+fn index_specs(config: &Config) -> Vec<IndexSpec> {
+    let retention = config.audit.retention_days;
+    vec![
+        IndexSpec::unique("nonce", doc! {"user_id": 1}),
+        IndexSpec::unique("device", doc! {"device_id": 1}),
+        IndexSpec::index("device", doc! {"mac": 1, "fingerprint": 1}),
+        IndexSpec::unique("binding", doc! {"user_id": 1, "device_id": 1}),
+        IndexSpec::index("binding", doc! {"device_id": 1}),
+        IndexSpec::unique("group", doc! {"group_id": 1}),
+        IndexSpec::index("group", doc! {"user_id": 1}),
+        IndexSpec::index("group", doc! {"shared_with": 1}),
+        IndexSpec::index("group", doc! {"device_ids": 1}),
+        IndexSpec::unique("revocation", doc! {"user_id": 1}),
+        IndexSpec::unique("ban", doc! {"kind": 1, "value": 1}),
+        IndexSpec::unique("connection", doc! {"node_id": 1, "conn_id": 1}),
+        IndexSpec::index("connection", doc! {"user_id": 1}),
+        IndexSpec::index("connection", doc! {"heartbeat_at": 1}),
+        IndexSpec::index("audit", doc! {"created_at": -1}),
+        IndexSpec::index("audit", doc! {"actor": 1, "created_at": -1}),
+        IndexSpec::index("audit", doc! {"target": 1, "created_at": -1}),
+        // 吊销时间之前签发的token全部过期后，吊销记录不再需要
+        IndexSpec::ttl(
+            "revocation",
+            "revoked_before",
+            Some(Duration::from_secs(TOKEN_LIFETIME_SECS as u64)),
+        ),
+        IndexSpec::ttl("ban", "expires_at", Some(Duration::ZERO)),
+        IndexSpec::ttl(
+            "audit",
+            "created_at",
+            (retention > 0).then(|| Duration::from_secs(retention * 24 * 60 * 60)),
+        ),
+    ]
+}
+
+// 启动时检查数据库结构版本，执行数据迁移并创建所需的索引
+// 数据库版本高于程序版本时拒绝启动，避免旧程序写坏新结构的数据
+// migrate为false时不执行迁移，存在未执行的迁移则拒绝启动
+pub async fn bootstrap(db: &Database, config: &Config, migrate: bool) -> Result<()> {
+    check_schema_version(db).await?;
+
+    if migrate {
+        migration::run(db, false).await?;
+    } else {
+        let pending = migration::pending(db).await?;
+        if !pending.is_empty() {
+            return Err(anyhow!(
+                "Pending migrations {:?}, run `deeplink-rs migrate` first",
+                pending
+            ));
+        }
+    }
+    create_indexes(db, config).await?;
+
+    let version = schema_version(db).await?;
+
+    if version < SCHEMA_VERSION {
+        set_schema_version(db, SCHEMA_VERSION).await?;
+        tracing::event!(
+            Level::INFO,
+            "Database schema upgraded from version {} to {}",
+            version,
+            SCHEMA_VERSION
+        );
+    }
+    Ok(())
+}
+
+pub async fn check_schema_version(db: &Database) -> Result<()> {
+    let version = schema_version(db).await?;
+    if version > SCHEMA_VERSION {
+        return Err(anyhow!(
+            "Database schema version {} is newer than supported version {}, please upgrade",
+            version,
+            SCHEMA_VERSION
+        ));
+    }
+    Ok(())
+}
+
+async fn schema_version(db: &Database) -> Result<u32> {
+    let collection = db.collection::<SchemaVersion>("schema");
+    let version = collection.find_one(doc! {"_id": "schema"}, None).await?;
+    Ok(version.map(|v| v.version).unwrap_or(0))
+}
+
+async fn set_schema_version(db: &Database, version: u32) -> Result<()> {
+    let collection = db.collection::<SchemaVersion>("schema");
+    let options = UpdateOptions::builder().upsert(true).build();
+    collection
+        .update_one(doc! {"_id": "schema"}, doc! {"$set": {"version": version}}, options)
+        .await?;
+    Ok(())
+}
+
+async fn create_indexes(db: &Database, config: &Config) -> Result<()> {
+    for spec in index_specs(config) {
+        if spec.ttl && !sync_ttl_index(db, &spec).await? {
+            continue;
+        }
+        let options = IndexOptions::builder()
+            .unique(spec.unique.then_some(true))
+            .expire_after(spec.expire_after)
+            .build();
+        let index = IndexModel::builder().keys(spec.keys.clone()).options(options).build();
+        db.collection::<Document>(spec.collection)
+            .create_index(index, None)
+            .await
+            .map_err(|e| {
+                anyhow!("Create index {} on {} failed: {}", spec.keys, spec.collection, e)
+            })?;
+    }
+    Ok(())
+}
+
+// 已存在的TTL索引按spec修改过期时间或删除，返回是否仍需创建索引
+// 调整保留天数后直接create_index会因选项不同而失败
+async fn sync_ttl_index(db: &Database, spec: &IndexSpec) -> Result<bool> {
+    let collection = db.collection::<Document>(spec.collection);
+    let existing = collection
+        .list_indexes(None)
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?
+        .into_iter()
+        .find(|index| index.keys == spec.keys);
+    let Some(existing) = existing else {
+        return Ok(spec.expire_after.is_some());
+    };
+    let current = existing.options.as_ref().and_then(|o| o.expire_after);
+    if current == spec.expire_after {
+        return Ok(false);
+    }
+    let name = existing.options.and_then(|o| o.name).unwrap_or_default();
+    match spec.expire_after {
+        Some(expire_after) => {
+            db.run_command(
+                doc! {
+                    "collMod": spec.collection,
+                    "index": {"name": &name, "expireAfterSeconds": expire_after.as_secs() as i64},
+                },
+                None,
+            )
+            .await?;
+        }
+        None => collection.drop_index(&name, None).await?,
+    }
+    tracing::event!(
+        Level::INFO,
+        "TTL index {} on {} changed to {:?}",
+        name,
+        spec.collection,
+        spec.expire_after
+    );
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn audit_ttl(config: &Config) -> Option<Duration> {
+        let specs = index_specs(config);
+        let spec = specs.iter().find(|s| s.ttl && s.collection == "audit").unwrap();
+        spec.expire_after
+    }
+
+    #[test]
+    fn audit_ttl_follows_retention() {
+        let mut config = Config::default();
+        config.audit.retention_days = 7;
+        assert_eq!(audit_ttl(&config), Some(Duration::from_secs(7 * 24 * 60 * 60)));
+        config.audit.retention_days = 0;
+        assert_eq!(audit_ttl(&config), None);
+    }
+
+    #[test]
+    fn index_keys_are_unique_per_collection() {
+        let specs = index_specs(&Config::default());
+        for (i, a) in specs.iter().enumerate() {
+            for b in &specs[i + 1..] {
+                assert!(a.collection != b.collection || a.keys != b.keys, "{:?}", a.keys);
+            }
+        }
+    }
+}
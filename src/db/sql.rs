@@ -0,0 +1,990 @@
+use anyhow::{anyhow, Error, Result};
+use axum::async_trait;
+use sqlx::{
+    any::{AnyPoolOptions, AnyRow},
+    migrate::{Migrate, Migrator},
+    AnyPool, Row,
+};
+use tracing::Level;
+
+use super::Storage;
+use crate::{
+    config::Config,
+    types::{
+        AuditAction, AuditEntry, AuditFilter, Ban, BanKind, ConnectionRecord, DeviceBinding,
+        DeviceGroup, DeviceInfo, Timestamp,
+    },
+    utils,
+};
+
+// 表结构见 migrations/sql，同一套SQL同时适用于SQLite和PostgreSQL
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations/sql");
+
+#[derive(Clone, Debug)]
+pub struct SqlStorage {
+    pub pool: AnyPool,
+}
+
+async fn connect(config: &Config) -> Result<AnyPool, Error> {
+    sqlx::any::install_default_drivers();
+    let pool = AnyPoolOptions::new()
+        .max_connections(config.sql.max_connections)
+        .connect(&config.sql.url)
+        .await?;
+    tracing::event!(Level::DEBUG, "Connected to {}", pool.connect_options().database_url);
+    Ok(pool)
+}
+
+// 尚未执行的迁移；数据库中存在程序不认识的迁移（结构比程序新）时返回错误
+async fn pending_migrations(pool: &AnyPool) -> Result<Vec<String>, Error> {
+    let mut conn = pool.acquire().await?;
+    conn.ensure_migrations_table().await?;
+    let applied = conn.list_applied_migrations().await?;
+    for migration in &applied {
+        if !MIGRATOR.iter().any(|m| m.version == migration.version) {
+            return Err(anyhow!(
+                "Database schema version {} is newer than supported, please upgrade",
+                migration.version
+            ));
+        }
+    }
+    Ok(MIGRATOR
+        .iter()
+        .filter(|m| !applied.iter().any(|a| a.version == m.version))
+        .map(|m| format!("{}_{}", m.version, m.description))
+        .collect())
+}
+
+// 生成 "$n, $n+1, ..." 形式的占位符，SQLite与PostgreSQL都支持
+fn placeholders(start: usize, count: usize) -> String {
+    (start..start + count).map(|i| format!("${}", i)).collect::<Vec<_>>().join(", ")
+}
+
+fn device_from_row(row: &AnyRow) -> Result<DeviceInfo, Error> {
+    Ok(DeviceInfo {
+        device_id: row.try_get("device_id")?,
+        device_name: row.try_get("device_name")?,
+        mac: row.try_get("mac")?,
+        fingerprint: row.try_get("fingerprint")?,
+        online: row.try_get::<i64, _>("online")? != 0,
+        add_time: Timestamp(bson::DateTime::from_millis(row.try_get("add_time")?)),
+        update_time: Timestamp(bson::DateTime::from_millis(row.try_get("update_time")?)),
+    })
+}
+
+fn binding_from_row(row: &AnyRow) -> Result<DeviceBinding, Error> {
+    let tags: String = row.try_get("tags")?;
+    Ok(DeviceBinding {
+        user_id: row.try_get("user_id")?,
+        device_id: row.try_get("device_id")?,
+        device_name: row.try_get("device_name")?,
+        tags: serde_json::from_str(&tags)?,
+    })
+}
+
+fn ban_from_row(row: &AnyRow) -> Result<Ban, Error> {
+    let kind: String = row.try_get("kind")?;
+    let expires_at: Option<i64> = row.try_get("expires_at")?;
+    Ok(Ban {
+        kind: BanKind::try_from(kind.as_str())?,
+        value: row.try_get("value")?,
+        reason: row.try_get("reason")?,
+        created_at: Timestamp(bson::DateTime::from_millis(row.try_get("created_at")?)),
+        expires_at: expires_at.map(|t| Timestamp(bson::DateTime::from_millis(t))),
+    })
+}
+
+fn connection_from_row(row: &AnyRow) -> Result<ConnectionRecord, Error> {
+    let conn_id: i64 = row.try_get("conn_id")?;
+    Ok(ConnectionRecord {
+        node_id: row.try_get("node_id")?,
+        conn_id: conn_id as u64,
+        user_id: row.try_get("user_id")?,
+        device_id: row.try_get("device_id")?,
+        addr: row.try_get("addr")?,
+        connected_at: Timestamp(bson::DateTime::from_millis(row.try_get("connected_at")?)),
+        heartbeat_at: Timestamp(bson::DateTime::from_millis(row.try_get("heartbeat_at")?)),
+    })
+}
+
+fn audit_from_row(row: &AnyRow) -> Result<AuditEntry, Error> {
+    let action: String = row.try_get("action")?;
+    Ok(AuditEntry {
+        action: AuditAction::try_from(action.as_str())?,
+        actor: row.try_get("actor")?,
+        target: row.try_get("target")?,
+        ip: row.try_get("ip")?,
+        user_agent: row.try_get("user_agent")?,
+        detail: row.try_get("detail")?,
+        created_at: Timestamp(bson::DateTime::from_millis(row.try_get("created_at")?)),
+    })
+}
+
+// 不区分大小写的子串匹配的LIKE模式，配合 LOWER(column) LIKE $n ESCAPE '\' 使用
+fn like_pattern(query: &str) -> String {
+    let escaped = query
+        .to_lowercase()
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+impl SqlStorage {
+    pub async fn new(config: &Config) -> Result<Self, Error> {
+        let pool = connect(config).await?;
+        let pending = pending_migrations(&pool).await?;
+        if !pending.is_empty() {
+            if !config.migrate_on_boot {
+                return Err(anyhow!(
+                    "Pending migrations {:?}, run `deeplink-rs migrate` first",
+                    pending
+                ));
+            }
+            MIGRATOR.run(&pool).await?;
+        }
+        Ok(SqlStorage { pool })
+    }
+
+    pub async fn migrate(config: &Config, dry_run: bool) -> Result<Vec<String>, Error> {
+        let pool = connect(config).await?;
+        let pending = pending_migrations(&pool).await?;
+        if !dry_run {
+            MIGRATOR.run(&pool).await?;
+        }
+        let outcome = if dry_run { "pending" } else { "applied" };
+        Ok(pending.into_iter().map(|m| format!("{}: {}", m, outcome)).collect())
+    }
+
+    async fn load_group(&self, row: &AnyRow) -> Result<DeviceGroup, Error> {
+        let group_id: String = row.try_get("group_id")?;
+        let device_ids = sqlx::query(
+            "SELECT device_id FROM group_device WHERE group_id = $1 ORDER BY device_id",
+        )
+        .bind(&group_id)
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(|row| row.try_get("device_id"))
+        .collect::<Result<_, _>>()?;
+        let shared_with =
+            sqlx::query("SELECT user_id FROM group_share WHERE group_id = $1 ORDER BY user_id")
+                .bind(&group_id)
+                .fetch_all(&self.pool)
+                .await?
+                .iter()
+                .map(|row| row.try_get("user_id"))
+                .collect::<Result<_, _>>()?;
+        Ok(DeviceGroup {
+            group_id,
+            user_id: row.try_get("user_id")?,
+            name: row.try_get("name")?,
+            device_ids,
+            shared_with,
+        })
+    }
+
+    async fn load_groups(&self, rows: Vec<AnyRow>) -> Result<Vec<DeviceGroup>, Error> {
+        let mut groups = vec![];
+        for row in &rows {
+            groups.push(self.load_group(row).await?);
+        }
+        Ok(groups)
+    }
+}
+
+#[async_trait]
+impl Storage for SqlStorage {
+    async fn ping(&self) -> Result<(), Error> {
+        sqlx::query("SELECT 1").execute(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn update_nonce(&self, user_id: &str, nonce: u64) -> Result<(), Error> {
+        sqlx::query(
+            "INSERT INTO nonce (user_id, nonce) VALUES ($1, $2) \
+             ON CONFLICT (user_id) DO UPDATE SET nonce = excluded.nonce",
+        )
+        .bind(user_id)
+        .bind(i64::try_from(nonce)?)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_nonce(&self, user_id: &str) -> Result<u64, Error> {
+        let row = sqlx::query("SELECT nonce FROM nonce WHERE user_id = $1")
+            .bind(user_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        match row {
+            Some(row) => Ok(u64::try_from(row.try_get::<i64, _>("nonce")?)?),
+            None => Ok(0),
+        }
+    }
+
+    async fn update_device(&self, mut device_info: DeviceInfo) -> Result<(), Error> {
+        device_info.update_time = utils::now();
+
+        sqlx::query(
+            "INSERT INTO device \
+             (device_id, device_name, mac, fingerprint, online, add_time, update_time) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7) \
+             ON CONFLICT (device_id) DO UPDATE SET \
+             device_name = excluded.device_name, mac = excluded.mac, \
+             fingerprint = excluded.fingerprint, online = excluded.online, \
+             add_time = excluded.add_time, update_time = excluded.update_time",
+        )
+        .bind(&device_info.device_id)
+        .bind(&device_info.device_name)
+        .bind(&device_info.mac)
+        .bind(&device_info.fingerprint)
+        .bind(device_info.online as i64)
+        .bind(device_info.add_time.0.timestamp_millis())
+        .bind(device_info.update_time.0.timestamp_millis())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn device_id_exist(&self, device_id: &str) -> Result<bool, Error> {
+        let row = sqlx::query("SELECT device_id FROM device WHERE device_id = $1")
+            .bind(device_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.is_some())
+    }
+
+    async fn get_devices(&self, device_ids: &[String]) -> Result<Vec<DeviceInfo>, Error> {
+        if device_ids.is_empty() {
+            return Ok(vec![]);
+        }
+        let sql = format!(
+            "SELECT * FROM device WHERE device_id IN ({})",
+            placeholders(1, device_ids.len())
+        );
+        let mut query = sqlx::query(&sql);
+        for device_id in device_ids {
+            query = query.bind(device_id);
+        }
+        query.fetch_all(&self.pool).await?.iter().map(device_from_row).collect()
+    }
+
+    async fn find_device(
+        &self,
+        device_ids: &[String],
+        mac: &str,
+        fingerprint: &str,
+    ) -> Result<Option<DeviceInfo>, Error> {
+        if device_ids.is_empty() {
+            return Ok(None);
+        }
+        let sql = format!(
+            "SELECT * FROM device WHERE mac = $1 AND fingerprint = $2 AND device_id IN ({})",
+            placeholders(3, device_ids.len())
+        );
+        let mut query = sqlx::query(&sql).bind(mac).bind(fingerprint);
+        for device_id in device_ids {
+            query = query.bind(device_id);
+        }
+        query
+            .fetch_optional(&self.pool)
+            .await?
+            .as_ref()
+            .map(device_from_row)
+            .transpose()
+    }
+
+    async fn set_device_online(&self, device_id: &str, online: bool) -> Result<(), Error> {
+        sqlx::query("UPDATE device SET online = $1, update_time = $2 WHERE device_id = $3")
+            .bind(online as i64)
+            .bind(utils::now().0.timestamp_millis())
+            .bind(device_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn bind_device(&self, binding: &DeviceBinding) -> Result<(), Error> {
+        sqlx::query(
+            "INSERT INTO binding (user_id, device_id, device_name, tags) VALUES ($1, $2, $3, $4) \
+             ON CONFLICT (user_id, device_id) DO UPDATE SET device_name = excluded.device_name",
+        )
+        .bind(&binding.user_id)
+        .bind(&binding.device_id)
+        .bind(&binding.device_name)
+        .bind(serde_json::to_string(&binding.tags)?)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn set_binding_tags(
+        &self,
+        user_id: &str,
+        device_id: &str,
+        tags: &[String],
+    ) -> Result<(), Error> {
+        sqlx::query("UPDATE binding SET tags = $1 WHERE user_id = $2 AND device_id = $3")
+            .bind(serde_json::to_string(tags)?)
+            .bind(user_id)
+            .bind(device_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn unbind_device(&self, user_id: &str, device_id: &str) -> Result<bool, Error> {
+        let mut tx = self.pool.begin().await?;
+        let result = sqlx::query("DELETE FROM binding WHERE user_id = $1 AND device_id = $2")
+            .bind(user_id)
+            .bind(device_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(
+            "DELETE FROM group_device WHERE device_id = $1 \
+             AND group_id IN (SELECT group_id FROM device_group WHERE user_id = $2)",
+        )
+        .bind(device_id)
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn get_bindings(&self, user_id: &str) -> Result<Vec<DeviceBinding>, Error> {
+        sqlx::query("SELECT * FROM binding WHERE user_id = $1")
+            .bind(user_id)
+            .fetch_all(&self.pool)
+            .await?
+            .iter()
+            .map(binding_from_row)
+            .collect()
+    }
+
+    async fn is_bound(&self, user_id: &str, device_id: &str) -> Result<bool, Error> {
+        let row =
+            sqlx::query("SELECT device_id FROM binding WHERE user_id = $1 AND device_id = $2")
+                .bind(user_id)
+                .bind(device_id)
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(row.is_some())
+    }
+
+    async fn get_device_viewers(&self, device_id: &str) -> Result<Vec<String>, Error> {
+        sqlx::query(
+            "SELECT user_id FROM binding WHERE device_id = $1 \
+             UNION SELECT s.user_id FROM group_share s \
+             JOIN group_device d ON d.group_id = s.group_id WHERE d.device_id = $1",
+        )
+        .bind(device_id)
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(|row| row.try_get("user_id").map_err(|e| anyhow!(e)))
+        .collect()
+    }
+
+    async fn insert_group(&self, group: &DeviceGroup) -> Result<(), Error> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("INSERT INTO device_group (group_id, user_id, name) VALUES ($1, $2, $3)")
+            .bind(&group.group_id)
+            .bind(&group.user_id)
+            .bind(&group.name)
+            .execute(&mut *tx)
+            .await?;
+        for device_id in &group.device_ids {
+            sqlx::query("INSERT INTO group_device (group_id, device_id) VALUES ($1, $2)")
+                .bind(&group.group_id)
+                .bind(device_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        for user_id in &group.shared_with {
+            sqlx::query("INSERT INTO group_share (group_id, user_id) VALUES ($1, $2)")
+                .bind(&group.group_id)
+                .bind(user_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn get_group(&self, group_id: &str) -> Result<Option<DeviceGroup>, Error> {
+        let row = sqlx::query("SELECT * FROM device_group WHERE group_id = $1")
+            .bind(group_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        match row {
+            Some(row) => Ok(Some(self.load_group(&row).await?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn get_groups(&self, user_id: &str) -> Result<Vec<DeviceGroup>, Error> {
+        let rows = sqlx::query("SELECT * FROM device_group WHERE user_id = $1")
+            .bind(user_id)
+            .fetch_all(&self.pool)
+            .await?;
+        self.load_groups(rows).await
+    }
+
+    async fn get_shared_groups(&self, user_id: &str) -> Result<Vec<DeviceGroup>, Error> {
+        let rows = sqlx::query(
+            "SELECT g.* FROM device_group g \
+             JOIN group_share s ON s.group_id = g.group_id WHERE s.user_id = $1",
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+        self.load_groups(rows).await
+    }
+
+    async fn rename_group(&self, group_id: &str, name: &str) -> Result<Option<DeviceGroup>, Error> {
+        sqlx::query("UPDATE device_group SET name = $1 WHERE group_id = $2")
+            .bind(name)
+            .bind(group_id)
+            .execute(&self.pool)
+            .await?;
+        self.get_group(group_id).await
+    }
+
+    async fn add_group_devices(
+        &self,
+        group_id: &str,
+        device_ids: &[String],
+    ) -> Result<Option<DeviceGroup>, Error> {
+        let mut tx = self.pool.begin().await?;
+        for device_id in device_ids {
+            sqlx::query(
+                "INSERT INTO group_device (group_id, device_id) VALUES ($1, $2) \
+                 ON CONFLICT (group_id, device_id) DO NOTHING",
+            )
+            .bind(group_id)
+            .bind(device_id)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        self.get_group(group_id).await
+    }
+
+    async fn remove_group_devices(
+        &self,
+        group_id: &str,
+        device_ids: &[String],
+    ) -> Result<Option<DeviceGroup>, Error> {
+        let mut tx = self.pool.begin().await?;
+        for device_id in device_ids {
+            sqlx::query("DELETE FROM group_device WHERE group_id = $1 AND device_id = $2")
+                .bind(group_id)
+                .bind(device_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        self.get_group(group_id).await
+    }
+
+    async fn share_group(
+        &self,
+        group_id: &str,
+        user_id: &str,
+    ) -> Result<Option<DeviceGroup>, Error> {
+        sqlx::query(
+            "INSERT INTO group_share (group_id, user_id) VALUES ($1, $2) \
+             ON CONFLICT (group_id, user_id) DO NOTHING",
+        )
+        .bind(group_id)
+        .bind(user_id)
+        .execute(&self.pool)
+        .await?;
+        self.get_group(group_id).await
+    }
+
+    async fn unshare_group(
+        &self,
+        group_id: &str,
+        user_id: &str,
+    ) -> Result<Option<DeviceGroup>, Error> {
+        sqlx::query("DELETE FROM group_share WHERE group_id = $1 AND user_id = $2")
+            .bind(group_id)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+        self.get_group(group_id).await
+    }
+
+    async fn delete_group(&self, group_id: &str) -> Result<(), Error> {
+        let mut tx = self.pool.begin().await?;
+        for sql in [
+            "DELETE FROM group_device WHERE group_id = $1",
+            "DELETE FROM group_share WHERE group_id = $1",
+            "DELETE FROM device_group WHERE group_id = $1",
+        ] {
+            sqlx::query(sql).bind(group_id).execute(&mut *tx).await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn search_users(
+        &self,
+        query: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<String>, Error> {
+        sqlx::query(
+            "SELECT user_id FROM nonce WHERE LOWER(user_id) LIKE $1 ESCAPE '\\' \
+             ORDER BY user_id LIMIT $2 OFFSET $3",
+        )
+        .bind(like_pattern(query))
+        .bind(limit as i64)
+        .bind(offset as i64)
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(|row| row.try_get("user_id").map_err(|e| anyhow!(e)))
+        .collect()
+    }
+
+    async fn search_devices(
+        &self,
+        query: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DeviceInfo>, Error> {
+        sqlx::query(
+            "SELECT * FROM device WHERE LOWER(device_id) LIKE $1 ESCAPE '\\' \
+             OR LOWER(device_name) LIKE $1 ESCAPE '\\' OR LOWER(mac) LIKE $1 ESCAPE '\\' \
+             ORDER BY device_id LIMIT $2 OFFSET $3",
+        )
+        .bind(like_pattern(query))
+        .bind(limit as i64)
+        .bind(offset as i64)
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(device_from_row)
+        .collect()
+    }
+
+    async fn revoke_tokens(&self, user_id: &str, before: Timestamp) -> Result<(), Error> {
+        sqlx::query(
+            "INSERT INTO token_revocation (user_id, revoked_before) VALUES ($1, $2) \
+             ON CONFLICT (user_id) DO UPDATE SET revoked_before = excluded.revoked_before",
+        )
+        .bind(user_id)
+        .bind(before.0.timestamp_millis())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn tokens_revoked_before(&self, user_id: &str) -> Result<Option<Timestamp>, Error> {
+        let row = sqlx::query("SELECT revoked_before FROM token_revocation WHERE user_id = $1")
+            .bind(user_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        match row {
+            Some(row) => {
+                Ok(Some(Timestamp(bson::DateTime::from_millis(row.try_get("revoked_before")?))))
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn upsert_ban(&self, ban: &Ban) -> Result<(), Error> {
+        sqlx::query(
+            "INSERT INTO ban (kind, value, reason, created_at, expires_at) \
+             VALUES ($1, $2, $3, $4, $5) \
+             ON CONFLICT (kind, value) DO UPDATE SET reason = excluded.reason, \
+             created_at = excluded.created_at, expires_at = excluded.expires_at",
+        )
+        .bind(ban.kind.as_str())
+        .bind(&ban.value)
+        .bind(&ban.reason)
+        .bind(ban.created_at.0.timestamp_millis())
+        .bind(ban.expires_at.map(|t| t.0.timestamp_millis()))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn delete_ban(&self, kind: BanKind, value: &str) -> Result<bool, Error> {
+        let result = sqlx::query("DELETE FROM ban WHERE kind = $1 AND value = $2")
+            .bind(kind.as_str())
+            .bind(value)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn get_bans(&self) -> Result<Vec<Ban>, Error> {
+        sqlx::query("SELECT * FROM ban ORDER BY kind, value")
+            .fetch_all(&self.pool)
+            .await?
+            .iter()
+            .map(ban_from_row)
+            .collect()
+    }
+
+    async fn upsert_connection(&self, record: &ConnectionRecord) -> Result<(), Error> {
+        sqlx::query(
+            "INSERT INTO connection \
+             (node_id, conn_id, user_id, device_id, addr, connected_at, heartbeat_at) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7) \
+             ON CONFLICT (node_id, conn_id) DO UPDATE SET user_id = excluded.user_id, \
+             device_id = excluded.device_id, heartbeat_at = excluded.heartbeat_at",
+        )
+        .bind(&record.node_id)
+        .bind(record.conn_id as i64)
+        .bind(&record.user_id)
+        .bind(&record.device_id)
+        .bind(&record.addr)
+        .bind(record.connected_at.0.timestamp_millis())
+        .bind(record.heartbeat_at.0.timestamp_millis())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn delete_connection(&self, node_id: &str, conn_id: u64) -> Result<(), Error> {
+        sqlx::query("DELETE FROM connection WHERE node_id = $1 AND conn_id = $2")
+            .bind(node_id)
+            .bind(conn_id as i64)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_node_connections(&self, node_id: &str) -> Result<u64, Error> {
+        let result = sqlx::query("DELETE FROM connection WHERE node_id = $1")
+            .bind(node_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn touch_node_connections(&self, node_id: &str, at: Timestamp) -> Result<(), Error> {
+        sqlx::query("UPDATE connection SET heartbeat_at = $1 WHERE node_id = $2")
+            .bind(at.0.timestamp_millis())
+            .bind(node_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_stale_connections(&self, before: Timestamp) -> Result<u64, Error> {
+        let result = sqlx::query("DELETE FROM connection WHERE heartbeat_at < $1")
+            .bind(before.0.timestamp_millis())
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn get_user_connections(&self, user_id: &str) -> Result<Vec<ConnectionRecord>, Error> {
+        sqlx::query("SELECT * FROM connection WHERE user_id = $1")
+            .bind(user_id)
+            .fetch_all(&self.pool)
+            .await?
+            .iter()
+            .map(connection_from_row)
+            .collect()
+    }
+
+    async fn insert_audit(&self, entry: &AuditEntry) -> Result<(), Error> {
+        sqlx::query(
+            "INSERT INTO audit (action, actor, target, ip, user_agent, detail, created_at) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7)",
+        )
+        .bind(entry.action.as_str())
+        .bind(&entry.actor)
+        .bind(&entry.target)
+        .bind(&entry.ip)
+        .bind(&entry.user_agent)
+        .bind(&entry.detail)
+        .bind(entry.created_at.0.timestamp_millis())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_audit(
+        &self,
+        filter: &AuditFilter,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<AuditEntry>, Error> {
+        // 只拼接占位符，参数按顺序绑定
+        let mut conditions = Vec::new();
+        let mut texts = Vec::new();
+        let mut times = Vec::new();
+        for (column, value) in [
+            ("action", filter.action.map(|a| a.as_str().to_owned())),
+            ("actor", filter.actor.clone()),
+            ("target", filter.target.clone()),
+        ] {
+            if let Some(value) = value {
+                texts.push(value);
+                conditions.push(format!("{} = ${}", column, texts.len()));
+            }
+        }
+        for (op, time) in [(">=", filter.since), ("<", filter.until)] {
+            if let Some(time) = time {
+                times.push(time.0.timestamp_millis());
+                conditions.push(format!("created_at {} ${}", op, texts.len() + times.len()));
+            }
+        }
+        let where_clause = if conditions.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", conditions.join(" AND "))
+        };
+        let n = texts.len() + times.len();
+        let sql = format!(
+            "SELECT * FROM audit {} ORDER BY created_at DESC LIMIT ${} OFFSET ${}",
+            where_clause,
+            n + 1,
+            n + 2
+        );
+
+        let mut query = sqlx::query(&sql);
+        for text in texts {
+            query = query.bind(text);
+        }
+        for time in times {
+            query = query.bind(time);
+        }
+        query
+            .bind(limit as i64)
+            .bind(offset as i64)
+            .fetch_all(&self.pool)
+            .await?
+            .iter()
+            .map(audit_from_row)
+            .collect()
+    }
+
+    async fn delete_audit_before(&self, before: Timestamp) -> Result<u64, Error> {
+        let result = sqlx::query("DELETE FROM audit WHERE created_at < $1")
+            .bind(before.0.timestamp_millis())
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SqlConfig;
+
+    // 每个连接各自拥有一个内存数据库，因此连接池只保留一个连接
+    async fn storage() -> SqlStorage {
+        let config = Config {
+            sql: SqlConfig { url: "sqlite::memory:".to_owned(), max_connections: 1 },
+            migrate_on_boot: true,
+            ..Default::default()
+        };
+        SqlStorage::new(&config).await.unwrap()
+    }
+
+    fn device(device_id: &str, mac: &str, fingerprint: &str) -> DeviceInfo {
+        DeviceInfo {
+            device_id: device_id.to_owned(),
+            device_name: device_id.to_owned(),
+            mac: mac.to_owned(),
+            fingerprint: fingerprint.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    fn binding(user_id: &str, device_id: &str) -> DeviceBinding {
+        DeviceBinding {
+            user_id: user_id.to_owned(),
+            device_id: device_id.to_owned(),
+            device_name: String::new(),
+            tags: vec![],
+        }
+    }
+
+    fn at(millis: i64) -> Timestamp {
+        Timestamp(bson::DateTime::from_millis(millis))
+    }
+
+    fn audit(action: AuditAction, actor: &str, created_at: i64) -> AuditEntry {
+        AuditEntry {
+            action,
+            actor: actor.to_owned(),
+            target: String::new(),
+            ip: String::new(),
+            user_agent: String::new(),
+            detail: String::new(),
+            created_at: at(created_at),
+        }
+    }
+
+    fn connection(node_id: &str, conn_id: u64, heartbeat_at: i64) -> ConnectionRecord {
+        ConnectionRecord {
+            node_id: node_id.to_owned(),
+            conn_id,
+            user_id: "alice".to_owned(),
+            device_id: "1".to_owned(),
+            addr: "127.0.0.1:1".to_owned(),
+            connected_at: at(0),
+            heartbeat_at: at(heartbeat_at),
+        }
+    }
+
+    #[tokio::test]
+    async fn nonce_round_trip() {
+        let db = storage().await;
+        assert_eq!(db.get_nonce("alice").await.unwrap(), 0);
+        db.update_nonce("alice", 5).await.unwrap();
+        db.update_nonce("alice", 7).await.unwrap();
+        assert_eq!(db.get_nonce("alice").await.unwrap(), 7);
+        assert_eq!(db.get_nonce("bob").await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn update_device_upserts() {
+        let db = storage().await;
+        db.update_device(device("1", "00:2B:67:6F:74:01", "fp")).await.unwrap();
+        let mut renamed = device("1", "00:2B:67:6F:74:01", "fp");
+        renamed.device_name = "renamed".to_owned();
+        renamed.online = true;
+        db.update_device(renamed).await.unwrap();
+
+        let devices = db.get_devices(&["1".to_owned()]).await.unwrap();
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].device_name, "renamed");
+        assert!(devices[0].online);
+        assert!(db.device_id_exist("1").await.unwrap());
+        assert!(!db.device_id_exist("2").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn find_device_among_given_ids() {
+        let db = storage().await;
+        db.update_device(device("1", "00:2B:67:6F:74:01", "fp")).await.unwrap();
+        let ids = ["1".to_owned()];
+
+        let found = db.find_device(&ids, "00:2B:67:6F:74:01", "fp").await.unwrap();
+        assert_eq!(found.unwrap().device_id, "1");
+        assert!(db.find_device(&ids, "00:2B:67:6F:74:01", "other").await.unwrap().is_none());
+        assert!(db
+            .find_device(&["2".to_owned()], "00:2B:67:6F:74:01", "fp")
+            .await
+            .unwrap()
+            .is_none());
+        assert!(db.find_device(&[], "00:2B:67:6F:74:01", "fp").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn bind_keeps_tags_and_unbind_leaves_groups() {
+        let db = storage().await;
+        db.update_device(device("1", "00:2B:67:6F:74:01", "")).await.unwrap();
+        db.bind_device(&binding("alice", "1")).await.unwrap();
+        db.set_binding_tags("alice", "1", &["gpu".to_owned()]).await.unwrap();
+        // 重复绑定只更新名称
+        let mut renamed = binding("alice", "1");
+        renamed.device_name = "mine".to_owned();
+        db.bind_device(&renamed).await.unwrap();
+
+        let bindings = db.get_bindings("alice").await.unwrap();
+        assert_eq!(bindings.len(), 1);
+        assert_eq!(bindings[0].device_name, "mine");
+        assert_eq!(bindings[0].tags, vec!["gpu".to_owned()]);
+        assert!(db.is_bound("alice", "1").await.unwrap());
+
+        let group = DeviceGroup {
+            group_id: "g".to_owned(),
+            user_id: "alice".to_owned(),
+            name: "lab".to_owned(),
+            device_ids: vec!["1".to_owned()],
+            shared_with: vec![],
+        };
+        db.insert_group(&group).await.unwrap();
+        assert!(db.unbind_device("alice", "1").await.unwrap());
+        assert!(!db.unbind_device("alice", "1").await.unwrap());
+        assert!(!db.is_bound("alice", "1").await.unwrap());
+        assert!(db.get_group("g").await.unwrap().unwrap().device_ids.is_empty());
+    }
+
+    #[tokio::test]
+    async fn shared_group_members_see_device() {
+        let db = storage().await;
+        db.bind_device(&binding("alice", "1")).await.unwrap();
+        let group = DeviceGroup {
+            group_id: "g".to_owned(),
+            user_id: "alice".to_owned(),
+            name: "lab".to_owned(),
+            device_ids: vec!["1".to_owned()],
+            shared_with: vec![],
+        };
+        db.insert_group(&group).await.unwrap();
+        assert_eq!(db.get_device_viewers("1").await.unwrap(), vec!["alice".to_owned()]);
+
+        let shared = db.share_group("g", "bob").await.unwrap().unwrap();
+        assert_eq!(shared.shared_with, vec!["bob".to_owned()]);
+        // 重复分享不报错
+        db.share_group("g", "bob").await.unwrap();
+        let mut viewers = db.get_device_viewers("1").await.unwrap();
+        viewers.sort();
+        assert_eq!(viewers, vec!["alice".to_owned(), "bob".to_owned()]);
+        assert_eq!(db.get_shared_groups("bob").await.unwrap().len(), 1);
+
+        db.unshare_group("g", "bob").await.unwrap();
+        assert_eq!(db.get_device_viewers("1").await.unwrap(), vec!["alice".to_owned()]);
+    }
+
+    // 文本与时间条件的占位符连续编号，LIMIT与OFFSET排在最后
+    #[tokio::test]
+    async fn audit_filter_placeholders() {
+        let db = storage().await;
+        db.insert_audit(&audit(AuditAction::Login, "alice", 1000)).await.unwrap();
+        db.insert_audit(&audit(AuditAction::Login, "alice", 2000)).await.unwrap();
+        db.insert_audit(&audit(AuditAction::Login, "alice", 3000)).await.unwrap();
+        db.insert_audit(&audit(AuditAction::Login, "bob", 2000)).await.unwrap();
+        db.insert_audit(&audit(AuditAction::LoginFailed, "alice", 2000)).await.unwrap();
+
+        let all = db.get_audit(&AuditFilter::default(), 10, 0).await.unwrap();
+        assert_eq!(all.len(), 5);
+        assert_eq!(all[0].created_at, at(3000));
+
+        let filter = AuditFilter {
+            action: Some(AuditAction::Login),
+            actor: Some("alice".to_owned()),
+            since: Some(at(1500)),
+            until: Some(at(3000)),
+            ..Default::default()
+        };
+        let entries = db.get_audit(&filter, 10, 0).await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].created_at, at(2000));
+
+        let filter = AuditFilter { since: Some(at(2000)), ..Default::default() };
+        assert_eq!(db.get_audit(&filter, 2, 1).await.unwrap().len(), 2);
+        assert_eq!(db.get_audit(&filter, 10, 3).await.unwrap().len(), 1);
+
+        assert_eq!(db.delete_audit_before(at(2000)).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn stale_connections_are_deleted() {
+        let db = storage().await;
+        db.upsert_connection(&connection("a", 1, 1000)).await.unwrap();
+        db.upsert_connection(&connection("b", 1, 1000)).await.unwrap();
+        db.touch_node_connections("a", at(5000)).await.unwrap();
+
+        assert_eq!(db.delete_stale_connections(at(3000)).await.unwrap(), 1);
+        let records = db.get_user_connections("alice").await.unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].node_id, "a");
+        assert_eq!(records[0].heartbeat_at, at(5000));
+    }
+}
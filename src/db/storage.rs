@@ -0,0 +1,182 @@
+use std::ops::Deref;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Error, Result};
+use axum::async_trait;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use tracing::Level;
+
+use super::{MeteredStorage, MongoStorage, SqlStorage};
+use crate::config::{Config, StorageBackend};
+use crate::types::{
+    AuditEntry, AuditFilter, Ban, BanKind, ConnectionRecord, DeviceBinding, DeviceGroup,
+    DeviceInfo, Timestamp,
+};
+
+// 存储层接口，MongoDB与SQL（SQLite/PostgreSQL）各有一个实现
+// 修改分组的方法返回修改后的分组，分组不存在时返回None
+#[async_trait]
+pub trait Storage: Send + Sync {
+    // 检查数据库是否可用（/readyz）
+    async fn ping(&self) -> Result<(), Error>;
+
+    async fn update_nonce(&self, user_id: &str, nonce: u64) -> Result<(), Error>;
+    async fn get_nonce(&self, user_id: &str) -> Result<u64, Error>;
+
+    async fn update_device(&self, device_info: DeviceInfo) -> Result<(), Error>;
+    async fn device_id_exist(&self, device_id: &str) -> Result<bool, Error>;
+    async fn get_devices(&self, device_ids: &[String]) -> Result<Vec<DeviceInfo>, Error>;
+    // 在给定的设备中查找mac与指纹都相同的设备
+    async fn find_device(
+        &self,
+        device_ids: &[String],
+        mac: &str,
+        fingerprint: &str,
+    ) -> Result<Option<DeviceInfo>, Error>;
+    async fn set_device_online(&self, device_id: &str, online: bool) -> Result<(), Error>;
+
+    // 重复绑定时只更新名称，保留已设置的标签
+    async fn bind_device(&self, binding: &DeviceBinding) -> Result<(), Error>;
+    async fn set_binding_tags(
+        &self,
+        user_id: &str,
+        device_id: &str,
+        tags: &[String],
+    ) -> Result<(), Error>;
+    // 解绑设备，同时将设备从该用户的所有分组中移除
+    async fn unbind_device(&self, user_id: &str, device_id: &str) -> Result<bool, Error>;
+    async fn get_bindings(&self, user_id: &str) -> Result<Vec<DeviceBinding>, Error>;
+    async fn is_bound(&self, user_id: &str, device_id: &str) -> Result<bool, Error>;
+    // 能看到该设备的用户：绑定了该设备，或该设备所在的分组分享给了该用户
+    async fn get_device_viewers(&self, device_id: &str) -> Result<Vec<String>, Error>;
+
+    async fn insert_group(&self, group: &DeviceGroup) -> Result<(), Error>;
+    async fn get_group(&self, group_id: &str) -> Result<Option<DeviceGroup>, Error>;
+    // 用户自己创建的分组
+    async fn get_groups(&self, user_id: &str) -> Result<Vec<DeviceGroup>, Error>;
+    // 其他用户分享给该用户的分组
+    async fn get_shared_groups(&self, user_id: &str) -> Result<Vec<DeviceGroup>, Error>;
+    async fn rename_group(&self, group_id: &str, name: &str) -> Result<Option<DeviceGroup>, Error>;
+    async fn add_group_devices(
+        &self,
+        group_id: &str,
+        device_ids: &[String],
+    ) -> Result<Option<DeviceGroup>, Error>;
+    async fn remove_group_devices(
+        &self,
+        group_id: &str,
+        device_ids: &[String],
+    ) -> Result<Option<DeviceGroup>, Error>;
+    async fn share_group(
+        &self,
+        group_id: &str,
+        user_id: &str,
+    ) -> Result<Option<DeviceGroup>, Error>;
+    async fn unshare_group(
+        &self,
+        group_id: &str,
+        user_id: &str,
+    ) -> Result<Option<DeviceGroup>, Error>;
+    async fn delete_group(&self, group_id: &str) -> Result<(), Error>;
+
+    // 管理接口：按子串（不区分大小写）搜索，结果按id排序
+    async fn search_users(
+        &self,
+        query: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<String>, Error>;
+    // 匹配device_id、设备名或mac
+    async fn search_devices(
+        &self,
+        query: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DeviceInfo>, Error>;
+
+    async fn revoke_tokens(&self, user_id: &str, before: Timestamp) -> Result<(), Error>;
+    async fn tokens_revoked_before(&self, user_id: &str) -> Result<Option<Timestamp>, Error>;
+
+    // 同一对象重复封禁时覆盖原记录
+    async fn upsert_ban(&self, ban: &Ban) -> Result<(), Error>;
+    async fn delete_ban(&self, kind: BanKind, value: &str) -> Result<bool, Error>;
+    async fn get_bans(&self) -> Result<Vec<Ban>, Error>;
+
+    // 已认证连接所在的节点，同一节点的同一连接重复写入时覆盖
+    async fn upsert_connection(&self, record: &ConnectionRecord) -> Result<(), Error>;
+    async fn delete_connection(&self, node_id: &str, conn_id: u64) -> Result<(), Error>;
+    // 节点启动时清理该节点上次运行遗留的记录，返回删除的条数
+    async fn delete_node_connections(&self, node_id: &str) -> Result<u64, Error>;
+    // 刷新该节点所有记录的heartbeat_at
+    async fn touch_node_connections(&self, node_id: &str, at: Timestamp) -> Result<(), Error>;
+    // 删除heartbeat_at早于before的记录（所在节点已退出），返回删除的条数
+    async fn delete_stale_connections(&self, before: Timestamp) -> Result<u64, Error>;
+    async fn get_user_connections(&self, user_id: &str) -> Result<Vec<ConnectionRecord>, Error>;
+
+    async fn insert_audit(&self, entry: &AuditEntry) -> Result<(), Error>;
+    // 按时间倒序
+    async fn get_audit(
+        &self,
+        filter: &AuditFilter,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<AuditEntry>, Error>;
+    // 删除before之前的审计日志，返回删除的条数
+    async fn delete_audit_before(&self, before: Timestamp) -> Result<u64, Error>;
+
+    async fn new_device_id(&self) -> Result<String, Error> {
+        for _ in 0..50 {
+            let num = StdRng::from_entropy().gen_range(100_000_000..1_000_000_000);
+            if !self.device_id_exist(&num.to_string()).await? {
+                return Ok(num.to_string());
+            }
+        }
+        Err(anyhow!("Err"))
+    }
+
+    async fn new_group_id(&self) -> Result<String, Error> {
+        for _ in 0..50 {
+            let group_id = format!("{:016x}", StdRng::from_entropy().gen::<u64>());
+            if self.get_group(&group_id).await?.is_none() {
+                return Ok(group_id);
+            }
+        }
+        Err(anyhow!("Err"))
+    }
+}
+
+// 各handler共享的存储句柄，按配置选择后端
+#[derive(Clone)]
+pub struct DB(Arc<dyn Storage>);
+
+impl Deref for DB {
+    type Target = dyn Storage;
+
+    fn deref(&self) -> &Self::Target {
+        self.0.as_ref()
+    }
+}
+
+impl DB {
+    pub async fn new(config: &Config) -> Self {
+        let storage: Result<Arc<dyn Storage>> = match config.storage {
+            StorageBackend::Mongo => MongoStorage::new(config).await.map(|s| Arc::new(s) as _),
+            StorageBackend::Sql => SqlStorage::new(config).await.map(|s| Arc::new(s) as _),
+        };
+        match storage {
+            Ok(storage) => DB(Arc::new(MeteredStorage::new(config.storage.name(), storage))),
+            Err(e) => {
+                tracing::event!(Level::ERROR, "Init storage failed: {:?}", e);
+                std::process::exit(-1);
+            }
+        }
+    }
+
+    // 执行数据迁移（deeplink-rs migrate），返回每个迁移的结果；dry_run时只返回待执行的迁移
+    pub async fn migrate(config: &Config, dry_run: bool) -> Result<Vec<String>, Error> {
+        match config.storage {
+            StorageBackend::Mongo => MongoStorage::migrate(config, dry_run).await,
+            StorageBackend::Sql => SqlStorage::migrate(config, dry_run).await,
+        }
+    }
+}
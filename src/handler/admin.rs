@@ -0,0 +1,316 @@
+use std::net::{IpAddr, SocketAddr};
+
+use axum::async_trait;
+use axum::extract::connect_info::ConnectInfo;
+use axum::extract::{FromRequestParts, Path, Query, State, TypedHeader};
+use axum::headers::{authorization::Bearer, Authorization, UserAgent};
+use axum::http::request::Parts;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::routing::{delete, get, post};
+use axum::{Json, Router};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::Level;
+
+use super::{audit, ban, cluster};
+use crate::jwt::HttpError;
+use crate::state::AppState;
+use crate::types::{AuditAction, AuditEntry, AuditFilter, Ban, BanKind, Timestamp};
+
+// 4000-4999为应用自定义的关闭码
+const CLOSE_ADMIN_DISCONNECT: u16 = 4000;
+const CLOSE_TOKEN_REVOKED: u16 = 4001;
+
+const DEFAULT_LIMIT: u32 = 50;
+const MAX_LIMIT: u32 = 500;
+
+// 管理接口，所有请求都需要带 Authorization: Bearer <admin.token>
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/users", get(list_users))
+        .route("/users/:user_id/revoke-tokens", post(revoke_tokens))
+        .route("/devices", get(list_devices))
+        .route("/connections", get(list_connections))
+        .route("/connections/:id", delete(disconnect))
+        .route("/bans", get(list_bans).post(ban))
+        .route("/bans/:kind/*value", delete(unban))
+        .route("/audit", get(list_audit))
+}
+
+// 审计日志中管理操作的actor
+const ADMIN_ACTOR: &str = "admin";
+
+// 通过管理凭证认证的请求，记录来源用于审计日志
+pub struct AdminAuth {
+    ip: IpAddr,
+    user_agent: String,
+}
+
+impl AdminAuth {
+    fn audit(&self, action: AuditAction, target: &str) -> AuditEntry {
+        audit::entry(action, ADMIN_ACTOR, target, self.ip, &self.user_agent)
+    }
+}
+
+#[async_trait]
+impl FromRequestParts<AppState> for AdminAuth {
+    type Rejection = HttpError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let Some(expected) = state.config.admin.token.as_deref() else {
+            return Err(HttpError::Auth);
+        };
+        let TypedHeader(Authorization(bearer)) =
+            TypedHeader::<Authorization<Bearer>>::from_request_parts(parts, state)
+                .await
+                .map_err(|_| HttpError::Auth)?;
+        let ConnectInfo(addr) = ConnectInfo::<SocketAddr>::from_request_parts(parts, state)
+            .await
+            .map_err(|_| HttpError::Internal)?;
+        if !constant_time_eq(bearer.token().as_bytes(), expected.as_bytes()) {
+            tracing::event!(Level::WARN, "Admin authentication failed from {}", addr.ip());
+            return Err(HttpError::Auth);
+        }
+        let user_agent = TypedHeader::<UserAgent>::from_request_parts(parts, state)
+            .await
+            .map(|TypedHeader(user_agent)| user_agent.to_string())
+            .unwrap_or_default();
+        Ok(AdminAuth { ip: addr.ip(), user_agent })
+    }
+}
+
+// 比较耗时与第一个不同字节的位置无关，避免通过响应时间猜测凭证
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SearchQuery {
+    #[serde(default)]
+    q: String,
+    limit: Option<u32>,
+    #[serde(default)]
+    offset: u32,
+}
+
+impl SearchQuery {
+    fn limit(&self) -> u32 {
+        self.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT)
+    }
+}
+
+// GET /admin/users?q=5Ebm&limit=50&offset=0
+async fn list_users(
+    _: AdminAuth,
+    State(state): State<AppState>,
+    Query(query): Query<SearchQuery>,
+) -> Result<Json<Value>, HttpError> {
+    let user_ids = state.db.search_users(query.q.trim(), query.limit(), query.offset).await?;
+    let mut users = Vec::with_capacity(user_ids.len());
+    for user_id in user_ids {
+        let devices = state.db.get_bindings(&user_id).await?;
+        // 所有节点上的连接
+        let online = state.db.get_user_connections(&user_id).await?.len();
+        users.push(json!({ "user_id": user_id, "devices": devices, "connections": online }));
+    }
+    Ok(Json(json!({ "users": users })))
+}
+
+// GET /admin/devices?q=manjaro&limit=50&offset=0
+async fn list_devices(
+    _: AdminAuth,
+    State(state): State<AppState>,
+    Query(query): Query<SearchQuery>,
+) -> Result<Json<Value>, HttpError> {
+    let devices = state.db.search_devices(query.q.trim(), query.limit(), query.offset).await?;
+    Ok(Json(json!({ "devices": devices })))
+}
+
+// 当前进程中的WebSocket连接；连接id只在节点内唯一，多节点部署时需分别访问各节点的管理接口
+async fn list_connections(_: AdminAuth, State(state): State<AppState>) -> Json<Value> {
+    let mut connections = state.connections.all();
+    connections.sort_by_key(|c| c.id);
+    let connections: Vec<Value> = connections
+        .iter()
+        .map(|c| {
+            let identity = c.identity();
+            let client = c.client();
+            json!({
+                "id": c.id,
+                "addr": c.addr.to_string(),
+                "user_agent": c.user_agent,
+                "connected_at": c.connected_at,
+                "user_id": identity.as_ref().map(|i| &i.user_id),
+                "device_id": identity.as_ref().map(|i| &i.device_id),
+                "protocol_version": client.as_ref().map(|c| c.protocol_version),
+                "client_version": client.as_ref().map(|c| &c.client_version),
+            })
+        })
+        .collect();
+    Json(json!({ "node_id": state.bus.node_id(), "connections": connections }))
+}
+
+// DELETE /admin/connections/12，只能关闭当前进程中的连接
+async fn disconnect(
+    admin: AdminAuth,
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+) -> Result<StatusCode, HttpError> {
+    let conn = state
+        .connections
+        .get(id)
+        .ok_or_else(|| HttpError::NotFound(format!("connection {}", id)))?;
+    tracing::event!(Level::INFO, "Admin disconnected connection {} from {}", id, conn.addr);
+    conn.close(CLOSE_ADMIN_DISCONNECT, "disconnected by admin");
+    let user_id = conn.identity().map(|i| i.user_id).unwrap_or_default();
+    let entry = admin.audit(AuditAction::AdminDisconnect, &id.to_string());
+    audit::record(&state, entry.with_detail(format!("user {}", user_id))).await;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// 使该用户此前签发的token全部失效，并断开其在所有节点上已认证的连接
+async fn revoke_tokens(
+    admin: AdminAuth,
+    State(state): State<AppState>,
+    Path(user_id): Path<String>,
+) -> Result<Json<Value>, HttpError> {
+    state.db.revoke_tokens(&user_id, Timestamp::now()).await?;
+    let disconnected =
+        cluster::close_user(&state, &user_id, CLOSE_TOKEN_REVOKED, "token revoked").await?;
+    tracing::event!(Level::INFO, "Admin revoked tokens of {}", user_id);
+    audit::record(&state, admin.audit(AuditAction::TokenRevoked, &user_id)).await;
+    Ok(Json(json!({ "user_id": user_id, "disconnected": disconnected })))
+}
+
+async fn list_bans(_: AdminAuth, State(state): State<AppState>) -> Result<Json<Value>, HttpError> {
+    let bans: Vec<Ban> = state.db.get_bans().await?.into_iter().filter(Ban::is_active).collect();
+    Ok(Json(json!({ "bans": bans })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BanParams {
+    kind: BanKind,
+    value: String,
+    #[serde(default)]
+    reason: String,
+    // 不设置时为永久封禁
+    expires_in_secs: Option<u64>,
+}
+
+// POST /admin/bans {"kind":"ip","value":"10.0.0.1","reason":"spam","expires_in_secs":3600}
+async fn ban(
+    admin: AdminAuth,
+    State(state): State<AppState>,
+    Json(params): Json<BanParams>,
+) -> Result<impl IntoResponse, HttpError> {
+    let value = normalize_ban_value(params.kind, &params.value)?;
+    let created_at = Timestamp::now();
+    let expires_at = match params.expires_in_secs {
+        Some(secs) => Some(
+            expires_at(created_at, secs)
+                .ok_or_else(|| HttpError::BadRequest("expires_in_secs is too large".to_owned()))?,
+        ),
+        None => None,
+    };
+    let ban = Ban { kind: params.kind, value, reason: params.reason, created_at, expires_at };
+    state.db.upsert_ban(&ban).await?;
+    state.bans.insert(ban.clone());
+    ban::enforce(&state);
+    tracing::event!(Level::INFO, "Admin banned {} {}", ban.kind.as_str(), ban.value);
+    let target = format!("{}:{}", ban.kind.as_str(), ban.value);
+    audit::record(&state, admin.audit(AuditAction::AdminBan, &target).with_detail(&ban.reason))
+        .await;
+    Ok((StatusCode::CREATED, Json(ban)))
+}
+
+// 溢出时返回None
+fn expires_at(created_at: Timestamp, secs: u64) -> Option<Timestamp> {
+    let millis = i64::try_from(secs)
+        .ok()
+        .and_then(|secs| secs.checked_mul(1000))
+        .and_then(|ms| created_at.0.timestamp_millis().checked_add(ms))?;
+    Some(Timestamp(bson::DateTime::from_millis(millis)))
+}
+
+// DELETE /admin/bans/ip/10.0.0.1 或 /admin/bans/ip/10.0.0.0/8
+async fn unban(
+    admin: AdminAuth,
+    State(state): State<AppState>,
+    Path((kind, value)): Path<(String, String)>,
+) -> Result<StatusCode, HttpError> {
+    let kind =
+        BanKind::try_from(kind.as_str()).map_err(|e| HttpError::BadRequest(e.to_string()))?;
+    let value = normalize_ban_value(kind, &value)?;
+    if !state.db.delete_ban(kind, &value).await? {
+        return Err(HttpError::NotFound(format!("ban {} {}", kind.as_str(), value)));
+    }
+    state.bans.remove(kind, &value);
+    tracing::event!(Level::INFO, "Admin unbanned {} {}", kind.as_str(), value);
+    let target = format!("{}:{}", kind.as_str(), value);
+    audit::record(&state, admin.audit(AuditAction::AdminUnban, &target)).await;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuditQuery {
+    action: Option<AuditAction>,
+    actor: Option<String>,
+    target: Option<String>,
+    // RFC 3339，如 2023-08-01T00:00:00Z
+    since: Option<Timestamp>,
+    until: Option<Timestamp>,
+    limit: Option<u32>,
+    #[serde(default)]
+    offset: u32,
+}
+
+// GET /admin/audit?actor=5Ebm...&action=login_failed&since=2023-08-01T00:00:00Z
+async fn list_audit(
+    _: AdminAuth,
+    State(state): State<AppState>,
+    Query(query): Query<AuditQuery>,
+) -> Result<Json<Value>, HttpError> {
+    let filter = AuditFilter {
+        action: query.action,
+        actor: query.actor,
+        target: query.target,
+        since: query.since,
+        until: query.until,
+    };
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+    let entries = state.db.get_audit(&filter, limit, query.offset).await?;
+    Ok(Json(json!({ "entries": entries })))
+}
+
+// IP与网段统一为标准格式，删除封禁时才能匹配
+fn normalize_ban_value(kind: BanKind, value: &str) -> Result<String, HttpError> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Err(HttpError::BadRequest("Empty ban value".to_owned()));
+    }
+    match kind {
+        BanKind::Ip => ban::normalize_ip(value).map_err(|e| HttpError::BadRequest(e.to_string())),
+        BanKind::User | BanKind::Device => Ok(value.to_owned()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expires_at_rejects_overflow() {
+        let created_at = Timestamp(bson::DateTime::from_millis(1_000));
+        let expires = expires_at(created_at, 60).unwrap();
+        assert_eq!(expires.0.timestamp_millis(), 61_000);
+        assert!(expires_at(created_at, u64::MAX).is_none());
+        assert!(expires_at(created_at, i64::MAX as u64 / 1000).is_none());
+    }
+}
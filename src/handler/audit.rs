@@ -0,0 +1,59 @@
+use std::net::IpAddr;
+use std::time::Duration;
+
+use tracing::Level;
+
+use super::connection::Connection;
+use crate::state::AppState;
+use crate::types::{AuditAction, AuditEntry, Timestamp};
+
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+pub fn entry(
+    action: AuditAction,
+    actor: &str,
+    target: &str,
+    ip: IpAddr,
+    user_agent: &str,
+) -> AuditEntry {
+    AuditEntry {
+        action,
+        actor: actor.to_owned(),
+        target: target.to_owned(),
+        ip: ip.to_string(),
+        user_agent: user_agent.to_owned(),
+        detail: String::new(),
+        created_at: Timestamp::now(),
+    }
+}
+
+// 来自WebSocket连接的事件，ip与user agent取自连接
+pub fn conn_entry(conn: &Connection, action: AuditAction, actor: &str, target: &str) -> AuditEntry {
+    entry(action, actor, target, conn.addr.ip(), &conn.user_agent)
+}
+
+// 写入审计日志，失败只记录错误，不影响请求本身
+pub async fn record(state: &AppState, entry: AuditEntry) {
+    if !state.config.audit.enabled {
+        return;
+    }
+    if let Err(e) = state.db.insert_audit(&entry).await {
+        tracing::event!(Level::ERROR, "Write audit log {} failed: {:?}", entry.action.as_str(), e);
+    }
+}
+
+// 定期删除超过保留天数的审计日志
+pub async fn prune(state: AppState) {
+    let retention = Duration::from_secs(state.config.audit.retention_days * 24 * 60 * 60);
+    let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+    loop {
+        interval.tick().await;
+        let now = Timestamp::now().0.timestamp_millis();
+        let before = Timestamp(bson::DateTime::from_millis(now - retention.as_millis() as i64));
+        match state.db.delete_audit_before(before).await {
+            Ok(0) => {}
+            Ok(deleted) => tracing::event!(Level::INFO, "Pruned {} audit log entries", deleted),
+            Err(e) => tracing::event!(Level::ERROR, "Prune audit log failed: {:?}", e),
+        }
+    }
+}
@@ -0,0 +1,217 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::RwLock;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use ipnet::IpNet;
+use tracing::Level;
+
+use crate::state::AppState;
+use crate::types::{Ban, BanKind};
+
+// 封禁命中已建立的连接时使用的关闭码
+pub const CLOSE_BANNED: u16 = 4002;
+
+// 封禁列表的内存缓存，检查封禁时不访问数据库
+// 本进程的修改直接更新缓存，其他进程的修改由watch定期从数据库同步
+#[derive(Debug, Default)]
+pub struct Bans {
+    inner: RwLock<BanSet>,
+}
+
+#[derive(Debug, Default)]
+struct BanSet {
+    // 用户与设备按值匹配
+    exact: HashMap<(BanKind, String), Ban>,
+    // IP封禁的值为单个IP或CIDR网段
+    networks: Vec<(IpNet, Ban)>,
+}
+
+impl BanSet {
+    fn insert(&mut self, ban: Ban) {
+        self.remove(ban.kind, &ban.value);
+        match ban.kind {
+            BanKind::Ip => match parse_network(&ban.value) {
+                Ok(network) => self.networks.push((network, ban)),
+                Err(e) => tracing::event!(Level::WARN, "Ignored invalid ip ban: {}", e),
+            },
+            BanKind::User | BanKind::Device => {
+                self.exact.insert((ban.kind, ban.value.clone()), ban);
+            }
+        }
+    }
+
+    fn remove(&mut self, kind: BanKind, value: &str) {
+        match kind {
+            BanKind::Ip => self.networks.retain(|(_, ban)| ban.value != value),
+            BanKind::User | BanKind::Device => {
+                self.exact.remove(&(kind, value.to_owned()));
+            }
+        }
+    }
+}
+
+impl Bans {
+    // 用数据库中的封禁列表替换缓存
+    pub fn replace(&self, bans: Vec<Ban>) {
+        let mut set = BanSet::default();
+        for ban in bans.into_iter().filter(Ban::is_active) {
+            set.insert(ban);
+        }
+        *self.inner.write().unwrap() = set;
+    }
+
+    pub fn insert(&self, ban: Ban) {
+        self.inner.write().unwrap().insert(ban);
+    }
+
+    pub fn remove(&self, kind: BanKind, value: &str) {
+        self.inner.write().unwrap().remove(kind, value);
+    }
+
+    // 用户或设备当前生效的封禁
+    pub fn get(&self, kind: BanKind, value: &str) -> Option<Ban> {
+        let inner = self.inner.read().unwrap();
+        inner
+            .exact
+            .get(&(kind, value.to_owned()))
+            .filter(|ban| ban.is_active())
+            .cloned()
+    }
+
+    // 包含该IP的生效中的封禁
+    pub fn ip(&self, ip: IpAddr) -> Option<Ban> {
+        let ip = ip.to_canonical();
+        let inner = self.inner.read().unwrap();
+        inner
+            .networks
+            .iter()
+            .find(|(network, ban)| network.contains(&ip) && ban.is_active())
+            .map(|(_, ban)| ban.clone())
+    }
+}
+
+// 单个IP视为/32或/128的网段
+fn parse_network(value: &str) -> Result<IpNet> {
+    value
+        .parse::<IpNet>()
+        .or_else(|_| value.parse::<IpAddr>().map(|ip| IpNet::from(ip.to_canonical())))
+        .map_err(|_| anyhow!("Invalid ip or CIDR: {}", value))
+}
+
+// IP封禁的值统一为标准格式：单个IP不带前缀，网段去掉主机位
+pub fn normalize_ip(value: &str) -> Result<String> {
+    if value.contains('/') {
+        Ok(parse_network(value)?.trunc().to_string())
+    } else {
+        let ip = value.parse::<IpAddr>().map_err(|_| anyhow!("Invalid ip: {}", value))?;
+        Ok(ip.to_canonical().to_string())
+    }
+}
+
+pub async fn reload(state: &AppState) -> Result<()> {
+    let bans = state.db.get_bans().await?;
+    state.bans.replace(bans);
+    Ok(())
+}
+
+// 断开被封禁的IP、用户或设备已建立的连接
+pub fn enforce(state: &AppState) {
+    for conn in state.connections.all() {
+        let ban = state.bans.ip(conn.addr.ip()).or_else(|| {
+            let identity = conn.identity()?;
+            state
+                .bans
+                .get(BanKind::User, &identity.user_id)
+                .or_else(|| state.bans.get(BanKind::Device, &identity.device_id))
+        });
+        if let Some(ban) = ban {
+            tracing::event!(
+                Level::INFO,
+                "Disconnecting connection {} (banned {} {})",
+                conn.id,
+                ban.kind.as_str(),
+                ban.value
+            );
+            conn.close(CLOSE_BANNED, "banned");
+        }
+    }
+}
+
+// 定期从数据库同步封禁列表（包括其他进程的修改），并断开新命中的连接
+pub async fn watch(state: AppState) {
+    let interval = Duration::from_secs(state.config.bans.refresh_interval_secs);
+    let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+    loop {
+        interval.tick().await;
+        match reload(&state).await {
+            Ok(()) => enforce(&state),
+            Err(e) => tracing::event!(Level::ERROR, "Reload bans failed: {:?}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Timestamp;
+
+    fn ban(kind: BanKind, value: &str, expires_at: Option<Timestamp>) -> Ban {
+        Ban {
+            kind,
+            value: value.to_owned(),
+            reason: String::new(),
+            created_at: Timestamp::now(),
+            expires_at,
+        }
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn cidr_ban_matches_addresses_in_range() {
+        let bans = Bans::default();
+        bans.insert(ban(BanKind::Ip, "10.0.0.0/8", None));
+        bans.insert(ban(BanKind::Ip, "2001:db8::/32", None));
+        assert!(bans.ip(ip("10.1.2.3")).is_some());
+        assert!(bans.ip(ip("11.0.0.1")).is_none());
+        assert!(bans.ip(ip("2001:db8::1")).is_some());
+        assert!(bans.ip(ip("2001:db9::1")).is_none());
+    }
+
+    #[test]
+    fn ipv4_ban_matches_mapped_ipv6_peer() {
+        let bans = Bans::default();
+        bans.insert(ban(BanKind::Ip, "192.168.1.7", None));
+        assert!(bans.ip(ip("::ffff:192.168.1.7")).is_some());
+        assert!(bans.ip(ip("192.168.1.8")).is_none());
+    }
+
+    #[test]
+    fn expired_and_removed_bans_do_not_match() {
+        let bans = Bans::default();
+        let past = Timestamp(bson::DateTime::from_millis(0));
+        bans.insert(ban(BanKind::Ip, "10.0.0.0/8", Some(past)));
+        bans.insert(ban(BanKind::User, "alice", Some(past)));
+        assert!(bans.ip(ip("10.0.0.1")).is_none());
+        assert!(bans.get(BanKind::User, "alice").is_none());
+
+        bans.insert(ban(BanKind::Device, "42", None));
+        assert!(bans.get(BanKind::Device, "42").is_some());
+        assert!(bans.get(BanKind::User, "42").is_none());
+        bans.remove(BanKind::Device, "42");
+        assert!(bans.get(BanKind::Device, "42").is_none());
+    }
+
+    #[test]
+    fn normalize_ip_truncates_host_bits() {
+        assert_eq!(normalize_ip("10.1.2.3/8").unwrap(), "10.0.0.0/8");
+        assert_eq!(normalize_ip("::ffff:10.0.0.1").unwrap(), "10.0.0.1");
+        assert_eq!(normalize_ip("2001:DB8::1").unwrap(), "2001:db8::1");
+        assert!(normalize_ip("10.0.0.256").is_err());
+        assert!(normalize_ip("10.0.0.0/33").is_err());
+    }
+}
@@ -0,0 +1,119 @@
+use std::collections::BTreeSet;
+use std::time::Duration;
+
+use anyhow::Result;
+use serde_json::Value;
+use tokio::sync::mpsc::UnboundedReceiver;
+use tracing::Level;
+
+use super::connection::Connection;
+use super::presence;
+use crate::bus::BusMessage;
+use crate::state::AppState;
+use crate::types::{ConnectionRecord, Timestamp};
+
+const STALE_HEARTBEATS: u32 = 3;
+
+// 处理本节点从消息总线收到的消息
+pub async fn run(state: AppState, mut rx: UnboundedReceiver<BusMessage>) {
+    while let Some(msg) = rx.recv().await {
+        match msg {
+            BusMessage::Presence { viewers, event } => presence::deliver(&state, &viewers, &event),
+            BusMessage::CloseUser { user_id, code, reason } => {
+                for conn in state.connections.all() {
+                    if conn.identity().is_some_and(|i| i.user_id == user_id) {
+                        conn.close(code, reason.clone());
+                    }
+                }
+            }
+            BusMessage::Deliver { user_id, method, params } => {
+                for conn in state.connections.all() {
+                    // 设备状态只推送给订阅了的连接
+                    let wanted =
+                        method != presence::METHOD || state.presence.is_subscribed(conn.id);
+                    if wanted && conn.identity().is_some_and(|i| i.user_id == user_id) {
+                        conn.push(&method, &params);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// 记录已认证连接所在的节点，失败只记录错误
+pub async fn register(state: &AppState, conn: &Connection) {
+    let Some(identity) = conn.identity() else {
+        return;
+    };
+    let record = ConnectionRecord {
+        node_id: state.bus.node_id().to_owned(),
+        conn_id: conn.id,
+        user_id: identity.user_id,
+        device_id: identity.device_id,
+        addr: conn.addr.to_string(),
+        connected_at: conn.connected_at,
+        heartbeat_at: Timestamp::now(),
+    };
+    if let Err(e) = state.db.upsert_connection(&record).await {
+        tracing::event!(Level::ERROR, "Save connection record failed: {:?}", e);
+    }
+}
+
+pub async fn unregister(state: &AppState, conn: &Connection) {
+    if conn.identity().is_none() {
+        return;
+    }
+    if let Err(e) = state.db.delete_connection(state.bus.node_id(), conn.id).await {
+        tracing::event!(Level::ERROR, "Delete connection record failed: {:?}", e);
+    }
+}
+
+// 定期刷新本节点的连接记录，并删除超过STALE_HEARTBEATS个周期未刷新的记录
+// 随机node_id的节点崩溃后不会再以同一node_id启动，其记录只能由其他节点清理
+pub async fn heartbeat(state: AppState) {
+    let period = Duration::from_secs(state.config.bus.heartbeat_interval_secs);
+    let mut interval = tokio::time::interval(period);
+    loop {
+        interval.tick().await;
+        let now = Timestamp::now();
+        if let Err(e) = state.db.touch_node_connections(state.bus.node_id(), now).await {
+            tracing::event!(Level::ERROR, "Refresh connection records failed: {:?}", e);
+            continue;
+        }
+        let stale = period * STALE_HEARTBEATS;
+        let before = now.0.timestamp_millis() - stale.as_millis() as i64;
+        match state
+            .db
+            .delete_stale_connections(Timestamp(bson::DateTime::from_millis(before)))
+            .await
+        {
+            Ok(0) => {}
+            Ok(n) => tracing::event!(Level::INFO, "Removed {} stale connection records", n),
+            Err(e) => tracing::event!(Level::ERROR, "Remove stale connections failed: {:?}", e),
+        }
+    }
+}
+
+// 推送给该用户在所有节点上的连接，按连接记录只发给该用户有连接的节点
+pub async fn deliver(state: &AppState, user_id: &str, method: &str, params: Value) -> Result<()> {
+    let records = state.db.get_user_connections(user_id).await?;
+    let nodes: BTreeSet<&str> = records.iter().map(|r| r.node_id.as_str()).collect();
+    let msg =
+        BusMessage::Deliver { user_id: user_id.to_owned(), method: method.to_owned(), params };
+    for node_id in nodes {
+        state.bus.send(node_id, &msg).await?;
+    }
+    Ok(())
+}
+
+// 关闭该用户在所有节点上的连接，返回关闭的连接数
+pub async fn close_user(state: &AppState, user_id: &str, code: u16, reason: &str) -> Result<usize> {
+    let records = state.db.get_user_connections(user_id).await?;
+    let nodes: BTreeSet<&str> = records.iter().map(|r| r.node_id.as_str()).collect();
+    let msg =
+        BusMessage::CloseUser { user_id: user_id.to_owned(), code, reason: reason.to_owned() };
+    for node_id in nodes {
+        state.bus.send(node_id, &msg).await?;
+    }
+    Ok(records.len())
+}
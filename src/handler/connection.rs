@@ -0,0 +1,283 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+
+use axum::extract::ws::{CloseFrame, Message};
+use serde::Serialize;
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::sync::Notify;
+use tracing::{Level, Span};
+
+use super::protocol::Protocol;
+use super::session::{Pushed, Session};
+use super::RpcError;
+use crate::config::WebSocketConfig;
+use crate::types::Timestamp;
+
+// 连接登录后绑定的用户与设备
+#[derive(Debug, Clone)]
+pub struct Identity {
+    pub user_id: String,
+    pub device_id: String,
+}
+
+// 客户端在hello握手中声明的版本
+#[derive(Debug, Clone)]
+pub struct ClientInfo {
+    pub protocol_version: u32,
+    pub client_version: String,
+}
+
+// 一个WebSocket连接，其他任务可通过它向该连接推送消息或关闭连接
+#[derive(Debug)]
+pub struct Connection {
+    pub id: u64,
+    pub addr: SocketAddr,
+    pub user_agent: String,
+    pub connected_at: Timestamp,
+    // 握手时协商的子协议，决定推送消息的格式
+    pub protocol: Protocol,
+    identity: RwLock<Option<Identity>>,
+    // 该连接最近一次登录使用的token，JSON-RPC请求未带token时使用
+    token: RwLock<Option<String>>,
+    // 未进行hello握手时为None
+    client: RwLock<Option<ClientInfo>>,
+    // 认证后建立的会话，推送的消息缓存在其中
+    session: RwLock<Option<Arc<Session>>>,
+    tx: mpsc::Sender<Message>,
+    // 待发送队列已满时通知连接任务断开
+    kick: Notify,
+    // 该连接的tracing span，认证后记录user_id与device_id
+    span: Span,
+}
+
+impl Connection {
+    pub fn identity(&self) -> Option<Identity> {
+        self.identity.read().unwrap().clone()
+    }
+
+    pub fn authenticate(&self, user_id: &str, device_id: &str) {
+        *self.identity.write().unwrap() =
+            Some(Identity { user_id: user_id.to_owned(), device_id: device_id.to_owned() });
+        self.span.record("user_id", user_id);
+        self.span.record("device_id", device_id);
+    }
+
+    pub fn token(&self) -> Option<String> {
+        self.token.read().unwrap().clone()
+    }
+
+    pub fn set_token(&self, token: &str) {
+        *self.token.write().unwrap() = Some(token.to_owned());
+    }
+
+    pub fn client(&self) -> Option<ClientInfo> {
+        self.client.read().unwrap().clone()
+    }
+
+    pub fn set_client(&self, client: ClientInfo) {
+        *self.client.write().unwrap() = Some(client);
+    }
+
+    pub fn session(&self) -> Option<Arc<Session>> {
+        self.session.read().unwrap().clone()
+    }
+
+    pub fn set_session(&self, session: Option<Arc<Session>>) {
+        *self.session.write().unwrap() = session;
+    }
+
+    // 连接已关闭或客户端处理过慢（队列已满，连接将被断开）时返回false
+    pub fn send(&self, msg: Message) -> bool {
+        match self.tx.try_send(msg) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => {
+                tracing::event!(
+                    parent: &self.span,
+                    Level::WARN,
+                    "Outbound queue is full, disconnecting slow consumer"
+                );
+                self.kick.notify_one();
+                false
+            }
+            Err(TrySendError::Closed(_)) => false,
+        }
+    }
+
+    // 推送消息，按该连接协商的协议编码；有会话时先缓存到会话中
+    pub fn push<T: Serialize>(&self, method: &str, params: &T) -> bool {
+        let params = match serde_json::to_value(params) {
+            Ok(params) => params,
+            Err(e) => {
+                tracing::event!(parent: &self.span, Level::ERROR, "Encode {} failed: {:?}", method, e);
+                return false;
+            }
+        };
+        let seq = self.session().map(|s| s.record(method, &params));
+        self.send_push(method, &params, seq)
+    }
+
+    // 补发恢复的会话中缓存的推送
+    pub fn replay(&self, pushed: &[Pushed]) {
+        for p in pushed {
+            if !self.send_push(&p.method, &p.params, Some(p.seq)) {
+                return;
+            }
+        }
+    }
+
+    fn send_push(&self, method: &str, params: &serde_json::Value, seq: Option<u64>) -> bool {
+        match self.protocol.push(method, params, seq) {
+            Ok(msg) => self.send(msg),
+            Err(e) => {
+                tracing::event!(parent: &self.span, Level::ERROR, "Encode {} failed: {:?}", method, e);
+                false
+            }
+        }
+    }
+
+    pub fn close(&self, code: u16, reason: impl Into<Cow<'static, str>>) -> bool {
+        self.send(Message::Close(Some(CloseFrame { code, reason: reason.into() })))
+    }
+
+    // 等待连接因处理过慢被断开
+    pub async fn kicked(&self) {
+        self.kick.notified().await
+    }
+}
+
+// 当前进程中所有WebSocket连接
+#[derive(Debug, Clone)]
+pub struct Connections {
+    inner: Arc<RwLock<HashMap<u64, Arc<Connection>>>>,
+    next_id: Arc<AtomicU64>,
+    // 有连接断开时通知，用于等待所有连接关闭
+    removed: Arc<Notify>,
+    // 已移除但断开后的清理（离线标记、推送状态变化等）还未完成的连接数
+    closing: Arc<AtomicUsize>,
+    outbound_queue: usize,
+    max_per_ip: usize,
+    max_per_user: usize,
+}
+
+impl Connections {
+    pub fn new(config: &WebSocketConfig) -> Self {
+        Connections {
+            inner: Default::default(),
+            next_id: Default::default(),
+            removed: Default::default(),
+            closing: Default::default(),
+            outbound_queue: config.outbound_queue,
+            max_per_ip: config.max_connections_per_ip,
+            max_per_user: config.max_connections_per_user,
+        }
+    }
+
+    // 注册连接，返回的receiver中是其他任务推送给该连接的消息
+    pub fn register(
+        &self,
+        addr: SocketAddr,
+        user_agent: String,
+        protocol: Protocol,
+        span: Span,
+    ) -> (Arc<Connection>, mpsc::Receiver<Message>) {
+        let (tx, rx) = mpsc::channel(self.outbound_queue);
+        let conn = Arc::new(Connection {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed) + 1,
+            addr,
+            user_agent,
+            connected_at: Timestamp::now(),
+            protocol,
+            identity: RwLock::new(None),
+            token: RwLock::new(None),
+            client: RwLock::new(None),
+            session: RwLock::new(None),
+            tx,
+            kick: Notify::new(),
+            span,
+        });
+        conn.span.record("conn_id", conn.id);
+        self.inner.write().unwrap().insert(conn.id, conn.clone());
+        (conn, rx)
+    }
+
+    // 该IP的连接数是否已达上限
+    pub fn ip_full(&self, ip: IpAddr) -> bool {
+        let inner = self.inner.read().unwrap();
+        inner.values().filter(|c| c.addr.ip() == ip).count() >= self.max_per_ip
+    }
+
+    // 连接以user_id认证前检查该用户的其他连接数，已以该用户认证的连接不重复计算
+    pub fn check_user(&self, conn: &Connection, user_id: &str) -> Result<(), RpcError> {
+        if conn.identity().is_some_and(|i| i.user_id == user_id) {
+            return Ok(());
+        }
+        if self.user_full(user_id) {
+            return Err(RpcError::TooManyConnections);
+        }
+        Ok(())
+    }
+
+    // 该用户已认证的连接数是否已达上限
+    pub fn user_full(&self, user_id: &str) -> bool {
+        let inner = self.inner.read().unwrap();
+        let count = inner
+            .values()
+            .filter(|c| c.identity().is_some_and(|i| i.user_id == user_id))
+            .count();
+        count >= self.max_per_user
+    }
+
+    // 移除连接，断开后的清理完成时须调用closed，在此之前wait_empty继续等待
+    pub fn unregister(&self, id: u64) {
+        self.closing.fetch_add(1, Ordering::SeqCst);
+        self.inner.write().unwrap().remove(&id);
+    }
+
+    pub fn closed(&self) {
+        self.closing.fetch_sub(1, Ordering::SeqCst);
+        self.removed.notify_waiters();
+    }
+
+    pub fn get(&self, id: u64) -> Option<Arc<Connection>> {
+        self.inner.read().unwrap().get(&id).cloned()
+    }
+
+    pub fn all(&self) -> Vec<Arc<Connection>> {
+        self.inner.read().unwrap().values().cloned().collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.read().unwrap().len()
+    }
+
+    // 该设备是否还有其他在线连接
+    pub fn device_connected(&self, device_id: &str) -> bool {
+        self.inner
+            .read()
+            .unwrap()
+            .values()
+            .any(|c| c.identity().is_some_and(|i| i.device_id == device_id))
+    }
+
+    // 有已认证连接的设备数
+    pub fn online_devices(&self) -> usize {
+        let inner = self.inner.read().unwrap();
+        let devices: HashSet<String> =
+            inner.values().filter_map(|c| c.identity()).map(|i| i.device_id).collect();
+        devices.len()
+    }
+
+    // 等待所有连接关闭并完成清理
+    pub async fn wait_empty(&self) {
+        loop {
+            let removed = self.removed.notified();
+            if self.len() == 0 && self.closing.load(Ordering::SeqCst) == 0 {
+                return;
+            }
+            removed.await;
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use std::fmt;
+use std::time::Duration;
+
+// 返回给客户端的错误，code为ResponseParams中的code（0表示成功）
+#[derive(Debug)]
+pub enum RpcError {
+    Internal(anyhow::Error),
+    MethodNotFound(String),
+    InvalidParams(String),
+    Unauthorized,
+    InvalidNonce,
+    InvalidSignature,
+    NotFound(String),
+    Forbidden,
+    // 请求过于频繁，Duration为建议的重试等待时间
+    RateLimited(Duration),
+    // 登录失败次数过多被临时锁定
+    LockedOut(Duration),
+    // 该用户的连接数已达上限
+    TooManyConnections,
+    // 用户或设备被封禁，附带封禁原因
+    Banned(String),
+    // 客户端的协议或版本低于服务端要求的最低版本
+    UpdateRequired(String),
+}
+
+impl RpcError {
+    pub fn code(&self) -> i32 {
+        match self {
+            RpcError::Internal(_) => 1,
+            RpcError::MethodNotFound(_) => 2,
+            RpcError::InvalidParams(_) => 3,
+            RpcError::Unauthorized => 4,
+            RpcError::InvalidNonce => 5,
+            RpcError::InvalidSignature => 6,
+            RpcError::NotFound(_) => 7,
+            RpcError::Forbidden => 8,
+            RpcError::RateLimited(_) => 9,
+            RpcError::LockedOut(_) => 10,
+            RpcError::TooManyConnections => 11,
+            RpcError::Banned(_) => 12,
+            RpcError::UpdateRequired(_) => 13,
+        }
+    }
+
+    // JSON-RPC 2.0的错误码：协议定义的错误使用标准错误码，其余沿用code()
+    pub fn jsonrpc_code(&self) -> i32 {
+        match self {
+            RpcError::Internal(_) => -32603,
+            RpcError::MethodNotFound(_) => -32601,
+            RpcError::InvalidParams(_) => -32602,
+            _ => self.code(),
+        }
+    }
+
+    // 客户端应等待的秒数，向上取整
+    pub fn retry_after(&self) -> Option<u64> {
+        match self {
+            RpcError::RateLimited(d) | RpcError::LockedOut(d) => {
+                Some(d.as_secs() + u64::from(d.subsec_nanos() > 0))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for RpcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            // 内部错误细节只记录日志，不返回给客户端
+            RpcError::Internal(_) => write!(f, "Internal error"),
+            RpcError::MethodNotFound(method) => write!(f, "Method not found: {}", method),
+            RpcError::InvalidParams(e) => write!(f, "Invalid params: {}", e),
+            RpcError::Unauthorized => write!(f, "Unauthorized"),
+            RpcError::InvalidNonce => write!(f, "Invalid nonce"),
+            RpcError::InvalidSignature => write!(f, "Invalid signature"),
+            RpcError::NotFound(what) => write!(f, "Not found: {}", what),
+            RpcError::Forbidden => write!(f, "Forbidden"),
+            RpcError::RateLimited(_) => write!(f, "Too many requests"),
+            RpcError::LockedOut(_) => write!(f, "Too many login failures"),
+            RpcError::TooManyConnections => write!(f, "Too many connections"),
+            RpcError::Banned(reason) => write!(f, "Banned: {}", reason),
+            RpcError::UpdateRequired(reason) => write!(f, "Update required: {}", reason),
+        }
+    }
+}
+
+impl From<anyhow::Error> for RpcError {
+    fn from(e: anyhow::Error) -> Self {
+        RpcError::Internal(e)
+    }
+}
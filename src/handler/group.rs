@@ -0,0 +1,143 @@
+use super::error::RpcError;
+use crate::jwt::Claims;
+use crate::types::{
+    CreateGroupParams, DeviceGroup, GroupDevicesParams, GroupIdParams, GroupListResult,
+    GroupResult, MessageResult, ShareGroupParams, UpdateGroupParams,
+};
+
+// 获取自己创建的分组，不存在或不属于该用户时返回错误
+async fn owned_group(
+    db: &crate::db::DB,
+    user_id: &str,
+    group_id: &str,
+) -> Result<DeviceGroup, RpcError> {
+    match db.get_group(group_id).await? {
+        Some(group) if group.user_id == user_id => Ok(group),
+        Some(_) => Err(RpcError::Forbidden),
+        None => Err(RpcError::NotFound(format!("group {}", group_id))),
+    }
+}
+
+// 获取自己创建或他人分享给自己的分组
+pub async fn visible_group(
+    db: &crate::db::DB,
+    user_id: &str,
+    group_id: &str,
+) -> Result<DeviceGroup, RpcError> {
+    match db.get_group(group_id).await? {
+        Some(group) if group.user_id == user_id => Ok(group),
+        Some(group) if group.shared_with.iter().any(|u| u == user_id) => Ok(group),
+        Some(_) => Err(RpcError::Forbidden),
+        None => Err(RpcError::NotFound(format!("group {}", group_id))),
+    }
+}
+
+fn check_name(name: &str) -> Result<String, RpcError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(RpcError::InvalidParams("group name is empty".to_owned()));
+    }
+    Ok(name.to_owned())
+}
+
+// {"id":1,"method":"createGroup","token":"...","params":{"name":"render farm"}}
+pub async fn create_group(
+    db: &crate::db::DB,
+    claims: &Claims,
+    params: CreateGroupParams,
+) -> Result<GroupResult, RpcError> {
+    let group = DeviceGroup {
+        group_id: db.new_group_id().await?,
+        user_id: claims.user_id.clone(),
+        name: check_name(&params.name)?,
+        device_ids: vec![],
+        shared_with: vec![],
+    };
+    db.insert_group(&group).await?;
+    Ok(GroupResult { group })
+}
+
+// {"id":1,"method":"updateGroup","token":"...","params":{"group_id":"...","name":"office"}}
+pub async fn update_group(
+    db: &crate::db::DB,
+    claims: &Claims,
+    params: UpdateGroupParams,
+) -> Result<GroupResult, RpcError> {
+    let name = check_name(&params.name)?;
+    owned_group(db, &claims.user_id, &params.group_id).await?;
+    updated(db.rename_group(&params.group_id, &name).await?)
+}
+
+pub async fn delete_group(
+    db: &crate::db::DB,
+    claims: &Claims,
+    params: GroupIdParams,
+) -> Result<MessageResult, RpcError> {
+    owned_group(db, &claims.user_id, &params.group_id).await?;
+    db.delete_group(&params.group_id).await?;
+    Ok(MessageResult::ok())
+}
+
+pub async fn get_group_list(
+    db: &crate::db::DB,
+    claims: &Claims,
+) -> Result<GroupListResult, RpcError> {
+    Ok(GroupListResult {
+        owned: db.get_groups(&claims.user_id).await?,
+        shared: db.get_shared_groups(&claims.user_id).await?,
+    })
+}
+
+// 只能将自己绑定的设备加入分组
+// {"id":1,"method":"addGroupDevices","token":"...","params":{"group_id":"...","device_ids":["684060212"]}}
+pub async fn add_group_devices(
+    db: &crate::db::DB,
+    claims: &Claims,
+    params: GroupDevicesParams,
+) -> Result<GroupResult, RpcError> {
+    owned_group(db, &claims.user_id, &params.group_id).await?;
+    for device_id in &params.device_ids {
+        if !db.is_bound(&claims.user_id, device_id).await? {
+            return Err(RpcError::Forbidden);
+        }
+    }
+    updated(db.add_group_devices(&params.group_id, &params.device_ids).await?)
+}
+
+pub async fn remove_group_devices(
+    db: &crate::db::DB,
+    claims: &Claims,
+    params: GroupDevicesParams,
+) -> Result<GroupResult, RpcError> {
+    owned_group(db, &claims.user_id, &params.group_id).await?;
+    updated(db.remove_group_devices(&params.group_id, &params.device_ids).await?)
+}
+
+// 将整个分组分享给其他用户，对方可在getDeviceList中看到分组内的设备
+// {"id":1,"method":"shareGroup","token":"...","params":{"group_id":"...","user_id":"5Ebm..."}}
+pub async fn share_group(
+    db: &crate::db::DB,
+    claims: &Claims,
+    params: ShareGroupParams,
+) -> Result<GroupResult, RpcError> {
+    if params.user_id == claims.user_id {
+        return Err(RpcError::InvalidParams("cannot share a group with yourself".to_owned()));
+    }
+    owned_group(db, &claims.user_id, &params.group_id).await?;
+    updated(db.share_group(&params.group_id, &params.user_id).await?)
+}
+
+pub async fn unshare_group(
+    db: &crate::db::DB,
+    claims: &Claims,
+    params: ShareGroupParams,
+) -> Result<GroupResult, RpcError> {
+    owned_group(db, &claims.user_id, &params.group_id).await?;
+    updated(db.unshare_group(&params.group_id, &params.user_id).await?)
+}
+
+fn updated(group: Option<DeviceGroup>) -> Result<GroupResult, RpcError> {
+    group
+        .map(|group| GroupResult { group })
+        .ok_or(RpcError::NotFound("group".to_owned()))
+}
@@ -0,0 +1,34 @@
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::Json;
+use serde_json::json;
+use tracing::Level;
+
+use crate::state::AppState;
+
+// 进程存活
+pub async fn healthz() -> impl IntoResponse {
+    Json(json!({ "status": "ok" }))
+}
+
+// 可以接收流量：数据库可用且不在关闭过程中
+pub async fn readyz(State(state): State<AppState>) -> impl IntoResponse {
+    if state.is_draining() {
+        let body = json!({ "status": "not ready", "reason": "draining" });
+        return (StatusCode::SERVICE_UNAVAILABLE, Json(body));
+    }
+    if let Err(e) = state.db.ping().await {
+        tracing::event!(Level::WARN, "Storage ping failed: {:?}", e);
+        let body = json!({ "status": "not ready", "reason": "storage unavailable" });
+        return (StatusCode::SERVICE_UNAVAILABLE, Json(body));
+    }
+    (StatusCode::OK, Json(json!({ "status": "ready" })))
+}
+
+pub async fn version() -> impl IntoResponse {
+    Json(json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "git_hash": env!("GIT_HASH"),
+    }))
+}
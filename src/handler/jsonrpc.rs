@@ -0,0 +1,124 @@
+use serde_json::{json, Value};
+
+use super::connection::Connection;
+use super::handlers::call;
+use crate::state::AppState;
+use crate::types::RequestParams;
+
+const PARSE_ERROR: i32 = -32700;
+const INVALID_REQUEST: i32 = -32600;
+
+// 带jsonrpc字段的消息按JSON-RPC 2.0处理
+pub fn is_jsonrpc(value: &Value) -> bool {
+    value.get("jsonrpc").is_some()
+}
+
+pub fn parse_error() -> Value {
+    error(Value::Null, PARSE_ERROR, "Parse error", None)
+}
+
+pub fn invalid_request(message: &str) -> Value {
+    error(Value::Null, INVALID_REQUEST, message, None)
+}
+
+// 处理一个JSON-RPC 2.0请求，通知（不带id的请求）不返回响应
+// token可放在params.token中，未提供时使用该连接最近一次登录的token
+// {"jsonrpc":"2.0","id":1,"method":"getDeviceList","params":{"token":"eyJ..."}}
+// {"jsonrpc":"2.0","id":1,"result":{"device_list":[]}}
+pub async fn handle(value: Value, state: &AppState, conn: &Connection) -> Option<Value> {
+    let Value::Object(mut request) = value else {
+        return Some(error(Value::Null, INVALID_REQUEST, "Invalid request", None));
+    };
+    let id = request.remove("id");
+    let reply_id = id.clone().unwrap_or(Value::Null);
+    if request.get("jsonrpc") != Some(&json!("2.0")) {
+        return Some(error(
+            reply_id,
+            INVALID_REQUEST,
+            "Invalid request: jsonrpc must be \"2.0\"",
+            None,
+        ));
+    }
+    let Some(Value::String(method)) = request.remove("method") else {
+        return Some(error(reply_id, INVALID_REQUEST, "Invalid request: missing method", None));
+    };
+    let mut params = match request.remove("params") {
+        None | Some(Value::Null) => json!({}),
+        Some(params) => params,
+    };
+    let token = params
+        .as_object_mut()
+        .and_then(|p| p.remove("token"))
+        .and_then(|t| t.as_str().map(str::to_owned))
+        .or_else(|| conn.token())
+        .unwrap_or_default();
+
+    let v = RequestParams { id: reply_id.as_u64().unwrap_or(0), method, token, params };
+    let response = call(&v, &reply_id, state, conn).await;
+    let id = id?;
+    Some(match response {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(e) => {
+            let data = e.retry_after().map(|retry_after| json!({ "retry_after": retry_after }));
+            error(id, e.jsonrpc_code(), &e.to_string(), data)
+        }
+    })
+}
+
+fn error(id: Value, code: i32, message: &str, data: Option<Value>) -> Value {
+    let mut error = json!({ "code": code, "message": message });
+    if let Some(data) = data {
+        error["data"] = data;
+    }
+    json!({ "jsonrpc": "2.0", "id": id, "error": error })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::handler::RpcError;
+
+    #[test]
+    fn protocol_errors_use_standard_codes() {
+        assert_eq!(RpcError::Internal(anyhow::anyhow!("db down")).jsonrpc_code(), -32603);
+        assert_eq!(RpcError::MethodNotFound("foo".into()).jsonrpc_code(), -32601);
+        assert_eq!(RpcError::InvalidParams("bad".into()).jsonrpc_code(), -32602);
+        // 其余错误沿用原有code
+        assert_eq!(RpcError::Unauthorized.jsonrpc_code(), 4);
+        assert_eq!(RpcError::Banned("spam".into()).jsonrpc_code(), 12);
+    }
+
+    #[test]
+    fn error_envelope() {
+        assert_eq!(
+            parse_error(),
+            json!({"jsonrpc": "2.0", "id": null, "error": {"code": -32700, "message": "Parse error"}})
+        );
+        assert_eq!(invalid_request("Empty batch")["error"]["code"], -32600);
+        let e = RpcError::RateLimited(Duration::from_millis(1500));
+        let data = e.retry_after().map(|retry_after| json!({ "retry_after": retry_after }));
+        assert_eq!(
+            error(json!("a"), e.jsonrpc_code(), &e.to_string(), data),
+            json!({
+                "jsonrpc": "2.0",
+                "id": "a",
+                "error": {"code": 9, "message": "Too many requests", "data": {"retry_after": 2}},
+            })
+        );
+    }
+
+    #[test]
+    fn internal_error_details_are_hidden() {
+        let e = RpcError::Internal(anyhow::anyhow!("connection refused"));
+        assert_eq!(e.to_string(), "Internal error");
+    }
+
+    #[test]
+    fn detects_jsonrpc_messages() {
+        assert!(is_jsonrpc(&json!({"jsonrpc": "2.0", "method": "hello"})));
+        assert!(!is_jsonrpc(&json!({"id": 1, "method": "hello", "token": ""})));
+        assert!(!is_jsonrpc(&json!([{"jsonrpc": "2.0"}])));
+    }
+}
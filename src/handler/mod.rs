@@ -1 +1,18 @@
+pub mod admin;
+pub mod audit;
+pub mod ban;
+pub mod cluster;
+pub mod connection;
 pub mod handlers;
+pub mod health;
+pub mod presence;
+pub mod protocol;
+pub mod ratelimit;
+pub mod session;
+
+mod device;
+mod error;
+mod group;
+mod jsonrpc;
+
+pub use error::RpcError;
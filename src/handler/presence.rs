@@ -0,0 +1,100 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::RwLock;
+
+use tracing::Level;
+
+use super::cluster;
+use crate::bus::BusMessage;
+use crate::state::AppState;
+use crate::types::{PresenceChange, PresenceEvent};
+
+// 推送消息的method
+pub const METHOD: &str = "presence";
+
+// 订阅了设备状态变化的连接及订阅时的用户，连接断开时取消订阅
+#[derive(Debug, Default)]
+pub struct Presence {
+    subscribers: RwLock<HashMap<u64, String>>,
+}
+
+impl Presence {
+    pub fn subscribe(&self, conn_id: u64, user_id: &str) {
+        self.subscribers.write().unwrap().insert(conn_id, user_id.to_owned());
+    }
+
+    pub fn is_subscribed(&self, conn_id: u64) -> bool {
+        self.subscribers.read().unwrap().contains_key(&conn_id)
+    }
+
+    pub fn unsubscribe(&self, conn_id: u64) -> bool {
+        self.subscribers.write().unwrap().remove(&conn_id).is_some()
+    }
+
+    fn subscribers(&self) -> Vec<(u64, String)> {
+        let subscribers = self.subscribers.read().unwrap();
+        subscribers.iter().map(|(id, user_id)| (*id, user_id.clone())).collect()
+    }
+}
+
+// 通过消息总线发给所有节点，由各节点推送给订阅了且能看到该设备（自己绑定或分享给自己）的连接
+// 失败只记录错误，不影响触发状态变化的请求
+pub async fn publish(state: &AppState, device_id: &str, event: PresenceChange) {
+    let viewers = match state.db.get_device_viewers(device_id).await {
+        Ok(viewers) => viewers,
+        Err(e) => {
+            tracing::event!(Level::ERROR, "Get viewers of device {} failed: {:?}", device_id, e);
+            return;
+        }
+    };
+    if viewers.is_empty() {
+        return;
+    }
+    let event = PresenceEvent { device_id: device_id.to_owned(), event };
+    if let Err(e) = state.bus.broadcast(&BusMessage::Presence { viewers, event }).await {
+        tracing::event!(Level::ERROR, "Publish presence of {} failed: {:?}", device_id, e);
+    }
+}
+
+// 只通知绑定该设备的用户自己，用于绑定上的名称和标签等其他用户看不到的变化
+// 只发给该用户有连接的节点，已断开、等待恢复的会话收不到
+pub async fn notify_owner(state: &AppState, user_id: &str, device_id: &str, event: PresenceChange) {
+    let event = PresenceEvent { device_id: device_id.to_owned(), event };
+    let result = match serde_json::to_value(&event) {
+        Ok(params) => cluster::deliver(state, user_id, METHOD, params).await,
+        Err(e) => Err(e.into()),
+    };
+    if let Err(e) = result {
+        tracing::event!(Level::ERROR, "Notify {} of device {} failed: {:?}", user_id, device_id, e);
+    }
+}
+
+// 推送给本节点上的订阅者
+pub fn deliver(state: &AppState, viewers: &[String], event: &PresenceEvent) {
+    let viewers: HashSet<&str> = viewers.iter().map(String::as_str).collect();
+    for (conn_id, user_id) in state.presence.subscribers() {
+        if !viewers.contains(user_id.as_str()) {
+            continue;
+        }
+        if let Some(conn) = state.connections.get(conn_id) {
+            conn.push(METHOD, event);
+        }
+    }
+    // 已断开但仍在保留期内的会话，缓存起来在恢复时补发
+    let detached: Vec<_> = state
+        .sessions
+        .all()
+        .into_iter()
+        .filter(|s| s.detached_presence() && viewers.contains(s.user_id.as_str()))
+        .collect();
+    if detached.is_empty() {
+        return;
+    }
+    match serde_json::to_value(event) {
+        Ok(params) => {
+            for session in detached {
+                session.record(METHOD, &params);
+            }
+        }
+        Err(e) => tracing::event!(Level::ERROR, "Encode presence failed: {:?}", e),
+    }
+}
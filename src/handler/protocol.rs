@@ -0,0 +1,156 @@
+use anyhow::{anyhow, Result};
+use axum::extract::ws::Message;
+use axum::http::HeaderValue;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+// 握手时通过Sec-WebSocket-Protocol协商的子协议
+// 之后该连接只使用JSON-RPC 2.0
+pub const JSONRPC: &str = "jsonrpc-2.0";
+// 二进制帧中的请求与响应使用MessagePack或CBOR编码，文本帧仍为JSON
+pub const MSGPACK: &str = "deeplink.msgpack";
+pub const CBOR: &str = "deeplink.cbor";
+
+pub const SUBPROTOCOLS: [&str; 3] = [JSONRPC, MSGPACK, CBOR];
+
+// 请求与响应格式的版本，hello握手中返回给客户端
+// 不兼容的修改时递增，并相应调整client.min_protocol_version
+pub const PROTOCOL_VERSION: u32 = 1;
+
+// 消息的编码，与请求使用相同的编码回复
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    Json,
+    MessagePack,
+    Cbor,
+}
+
+impl Codec {
+    pub fn decode(&self, data: &[u8]) -> Result<Value> {
+        match self {
+            Codec::Json => serde_json::from_slice(data).map_err(|e| anyhow!(e)),
+            Codec::MessagePack => rmp_serde::from_slice(data).map_err(|e| anyhow!(e)),
+            Codec::Cbor => ciborium::de::from_reader(data).map_err(|e| anyhow!(e)),
+        }
+    }
+
+    // JSON为文本帧，其他为二进制帧
+    pub fn encode<T: Serialize>(&self, value: &T) -> Result<Message> {
+        match self {
+            Codec::Json => Ok(Message::Text(serde_json::to_string(value)?)),
+            // 结构体编码为map，与JSON中的字段一致
+            Codec::MessagePack => Ok(Message::Binary(rmp_serde::to_vec_named(value)?)),
+            Codec::Cbor => {
+                let mut data = Vec::new();
+                ciborium::ser::into_writer(value, &mut data)?;
+                Ok(Message::Binary(data))
+            }
+        }
+    }
+}
+
+// 连接协商的子协议
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Protocol {
+    pub jsonrpc_only: bool,
+    // 二进制帧的编码，未协商时不接受二进制帧
+    pub binary: Option<Codec>,
+}
+
+impl Protocol {
+    pub fn negotiated(subprotocol: Option<&HeaderValue>) -> Self {
+        match subprotocol.and_then(|p| p.to_str().ok()) {
+            Some(JSONRPC) => Protocol { jsonrpc_only: true, binary: None },
+            Some(MSGPACK) => Protocol { jsonrpc_only: false, binary: Some(Codec::MessagePack) },
+            Some(CBOR) => Protocol { jsonrpc_only: false, binary: Some(Codec::Cbor) },
+            _ => Protocol::default(),
+        }
+    }
+
+    // 服务端主动推送的消息：JSON-RPC连接为通知，其他连接为id为0的响应
+    // 使用二进制编码的连接推送二进制帧；有会话的连接带seq，用于恢复会话时补发
+    pub fn push(&self, method: &str, params: &Value, seq: Option<u64>) -> Result<Message> {
+        let codec = self.binary.unwrap_or(Codec::Json);
+        let mut msg = if self.jsonrpc_only {
+            json!({ "jsonrpc": "2.0", "method": method, "params": params })
+        } else {
+            json!({ "id": 0, "method": method, "code": 0, "result": params })
+        };
+        if let Some(seq) = seq {
+            msg["seq"] = seq.into();
+        }
+        codec.encode(&msg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request() -> Value {
+        json!({"id": 7, "method": "getNonce", "token": "", "params": {"user_id": "alice"}})
+    }
+
+    fn payload(msg: Message) -> Vec<u8> {
+        match msg {
+            Message::Text(text) => text.into_bytes(),
+            Message::Binary(data) => data,
+            other => panic!("unexpected frame {:?}", other),
+        }
+    }
+
+    #[test]
+    fn codecs_round_trip() {
+        for codec in [Codec::Json, Codec::MessagePack, Codec::Cbor] {
+            let msg = codec.encode(&request()).unwrap();
+            assert_eq!(matches!(msg, Message::Text(_)), codec == Codec::Json);
+            assert_eq!(codec.decode(&payload(msg)).unwrap(), request(), "{:?}", codec);
+        }
+    }
+
+    #[test]
+    fn msgpack_encodes_structs_as_maps() {
+        #[derive(Serialize)]
+        struct Reply {
+            code: i32,
+        }
+        let data = payload(Codec::MessagePack.encode(&Reply { code: 0 }).unwrap());
+        assert_eq!(Codec::MessagePack.decode(&data).unwrap(), json!({"code": 0}));
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        assert!(Codec::Json.decode(b"{").is_err());
+        assert!(Codec::MessagePack.decode(&[0xc1]).is_err());
+        assert!(Codec::Cbor.decode(&[0xff]).is_err());
+    }
+
+    #[test]
+    fn negotiates_subprotocols() {
+        let negotiated = |p: &'static str| Protocol::negotiated(Some(&HeaderValue::from_static(p)));
+        assert!(negotiated(JSONRPC).jsonrpc_only);
+        assert_eq!(negotiated(MSGPACK).binary, Some(Codec::MessagePack));
+        assert_eq!(negotiated(CBOR).binary, Some(Codec::Cbor));
+        let default = Protocol::negotiated(None);
+        assert!(!default.jsonrpc_only && default.binary.is_none());
+    }
+
+    #[test]
+    fn pushes_match_the_connection_format() {
+        let params = json!({"device_id": "42"});
+        let jsonrpc = Protocol { jsonrpc_only: true, binary: None };
+        let msg = payload(jsonrpc.push("presence", &params, None).unwrap());
+        assert_eq!(
+            Codec::Json.decode(&msg).unwrap(),
+            json!({"jsonrpc": "2.0", "method": "presence", "params": params})
+        );
+
+        let cbor = Protocol { jsonrpc_only: false, binary: Some(Codec::Cbor) };
+        let msg = cbor.push("presence", &params, Some(3)).unwrap();
+        assert!(matches!(msg, Message::Binary(_)));
+        assert_eq!(
+            Codec::Cbor.decode(&payload(msg)).unwrap(),
+            json!({"id": 0, "method": "presence", "code": 0, "result": params, "seq": 3})
+        );
+    }
+}
@@ -0,0 +1,301 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use tracing::Level;
+
+use super::RpcError;
+use crate::config::{BucketConfig, RateLimitConfig};
+use crate::metrics;
+
+// 清理已回满的令牌桶与过期的失败记录的间隔
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+// 按key（IP、user_id、连接）分别计数的令牌桶
+struct Limiter<K> {
+    config: BucketConfig,
+    buckets: Mutex<HashMap<K, Bucket>>,
+}
+
+impl<K: Hash + Eq> Limiter<K> {
+    fn new(config: BucketConfig) -> Self {
+        Limiter { config, buckets: Mutex::new(HashMap::new()) }
+    }
+
+    // 取一个令牌，没有令牌时返回需要等待的时间
+    fn check(&self, key: K) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets
+            .entry(key)
+            .or_insert(Bucket { tokens: self.config.burst as f64, updated: now });
+        bucket.tokens = (bucket.tokens
+            + now.duration_since(bucket.updated).as_secs_f64() * self.config.per_second)
+            .min(self.config.burst as f64);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / self.config.per_second))
+        }
+    }
+
+    fn remove(&self, key: &K) {
+        self.buckets.lock().unwrap().remove(key);
+    }
+
+    // 已回满的桶与新建的桶等价，可以删除
+    fn sweep(&self) {
+        let now = Instant::now();
+        let full = Duration::from_secs_f64(self.config.burst as f64 / self.config.per_second);
+        self.buckets.lock().unwrap().retain(|_, b| now.duration_since(b.updated) < full);
+    }
+}
+
+struct Failures {
+    count: u32,
+    first: Instant,
+    locked_until: Option<Instant>,
+}
+
+// 登录签名错误次数过多时临时锁定
+struct Lockout {
+    max_failures: u32,
+    window: Duration,
+    duration: Duration,
+    failures: Mutex<HashMap<String, Failures>>,
+}
+
+impl Lockout {
+    fn check(&self, key: &str) -> Result<(), Duration> {
+        let now = Instant::now();
+        match self.failures.lock().unwrap().get(key).and_then(|f| f.locked_until) {
+            Some(until) if until > now => Err(until - now),
+            _ => Ok(()),
+        }
+    }
+
+    fn failed(&self, key: String) {
+        let now = Instant::now();
+        let mut failures = self.failures.lock().unwrap();
+        let entry =
+            failures
+                .entry(key)
+                .or_insert(Failures { count: 0, first: now, locked_until: None });
+        if now.duration_since(entry.first) > self.window {
+            *entry = Failures { count: 0, first: now, locked_until: None };
+        }
+        entry.count += 1;
+        if entry.count >= self.max_failures {
+            entry.locked_until = Some(now + self.duration);
+        }
+    }
+
+    fn succeeded(&self, key: &str) {
+        self.failures.lock().unwrap().remove(key);
+    }
+
+    fn sweep(&self) {
+        let now = Instant::now();
+        self.failures.lock().unwrap().retain(|_, f| {
+            f.locked_until.is_some_and(|until| until > now)
+                || now.duration_since(f.first) <= self.window
+        });
+    }
+}
+
+// 请求限流：每个连接的所有请求，以及未认证接口（getNonce/registerDevice/login）按IP和user_id限流
+pub struct RateLimits {
+    enabled: bool,
+    connection: Limiter<u64>,
+    ip: Limiter<IpAddr>,
+    user: Limiter<String>,
+    lockout: Lockout,
+}
+
+impl RateLimits {
+    pub fn new(config: &RateLimitConfig) -> Self {
+        RateLimits {
+            enabled: config.enabled,
+            connection: Limiter::new(config.per_connection),
+            ip: Limiter::new(config.per_ip),
+            user: Limiter::new(config.per_user),
+            lockout: Lockout {
+                max_failures: config.max_login_failures,
+                window: Duration::from_secs(config.login_failure_window_secs),
+                duration: Duration::from_secs(config.lockout_secs),
+                failures: Mutex::new(HashMap::new()),
+            },
+        }
+    }
+
+    pub fn check_connection(&self, id: u64) -> Result<(), RpcError> {
+        self.check("connection", || self.connection.check(id))
+    }
+
+    pub fn check_anonymous(&self, ip: IpAddr, user_id: Option<&str>) -> Result<(), RpcError> {
+        self.check("ip", || self.ip.check(ip))?;
+        match user_id {
+            Some(user_id) => self.check("user", || self.user.check(user_id.to_owned())),
+            None => Ok(()),
+        }
+    }
+
+    // 该IP或user_id是否因签名错误过多被锁定
+    pub fn check_login(&self, ip: IpAddr, user_id: &str) -> Result<(), RpcError> {
+        if !self.enabled {
+            return Ok(());
+        }
+        for key in [ip_key(ip), user_key(user_id)] {
+            if let Err(retry_after) = self.lockout.check(&key) {
+                metrics::RATE_LIMITED.with_label_values(&["lockout"]).inc();
+                return Err(RpcError::LockedOut(retry_after));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn login_failed(&self, ip: IpAddr, user_id: &str) {
+        if !self.enabled {
+            return;
+        }
+        self.lockout.failed(ip_key(ip));
+        self.lockout.failed(user_key(user_id));
+        if self.lockout.check(&user_key(user_id)).is_err() {
+            tracing::event!(Level::WARN, "Too many login failures, {} is locked out", user_id);
+        }
+    }
+
+    // 登录成功后清除该用户的失败记录；IP的记录保留，避免用自己的账号重置计数
+    pub fn login_succeeded(&self, user_id: &str) {
+        self.lockout.succeeded(&user_key(user_id));
+    }
+
+    pub fn remove_connection(&self, id: u64) {
+        self.connection.remove(&id);
+    }
+
+    fn check(
+        &self,
+        scope: &'static str,
+        check: impl FnOnce() -> Result<(), Duration>,
+    ) -> Result<(), RpcError> {
+        if !self.enabled {
+            return Ok(());
+        }
+        check().map_err(|retry_after| {
+            metrics::RATE_LIMITED.with_label_values(&[scope]).inc();
+            RpcError::RateLimited(retry_after)
+        })
+    }
+
+    fn sweep(&self) {
+        self.ip.sweep();
+        self.user.sweep();
+        self.lockout.sweep();
+    }
+}
+
+fn ip_key(ip: IpAddr) -> String {
+    format!("ip:{}", ip)
+}
+
+fn user_key(user_id: &str) -> String {
+    format!("user:{}", user_id)
+}
+
+// 定期清理，避免大量不同IP/user_id使内存无限增长
+pub async fn sweep(limits: std::sync::Arc<RateLimits>) {
+    let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+    loop {
+        interval.tick().await;
+        limits.sweep();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits() -> RateLimits {
+        RateLimits::new(&RateLimitConfig {
+            per_connection: BucketConfig { burst: 3, per_second: 1.0 },
+            per_ip: BucketConfig { burst: 2, per_second: 0.5 },
+            max_login_failures: 2,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn bucket_allows_burst_then_limits() {
+        let limiter = Limiter::new(BucketConfig { burst: 3, per_second: 1.0 });
+        for _ in 0..3 {
+            assert!(limiter.check(1).is_ok());
+        }
+        let retry_after = limiter.check(1).unwrap_err();
+        assert!(retry_after > Duration::ZERO && retry_after <= Duration::from_secs(1));
+        // 不同key互不影响
+        assert!(limiter.check(2).is_ok());
+    }
+
+    #[test]
+    fn bucket_refills_over_time() {
+        let limiter = Limiter::new(BucketConfig { burst: 1, per_second: 1.0 });
+        assert!(limiter.check(1).is_ok());
+        assert!(limiter.check(1).is_err());
+        limiter.buckets.lock().unwrap().get_mut(&1).unwrap().updated -= Duration::from_secs(1);
+        assert!(limiter.check(1).is_ok());
+    }
+
+    #[test]
+    fn removed_connection_gets_a_new_bucket() {
+        let limits = limits();
+        for _ in 0..3 {
+            limits.check_connection(1).unwrap();
+        }
+        assert!(matches!(limits.check_connection(1), Err(RpcError::RateLimited(_))));
+        limits.remove_connection(1);
+        assert!(limits.check_connection(1).is_ok());
+    }
+
+    #[test]
+    fn lockout_after_max_failures() {
+        let limits = limits();
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        limits.login_failed(ip, "alice");
+        assert!(limits.check_login(ip, "alice").is_ok());
+        limits.login_failed(ip, "alice");
+        assert!(matches!(limits.check_login(ip, "alice"), Err(RpcError::LockedOut(_))));
+        // IP被锁定时其他用户也不能登录
+        assert!(limits.check_login(ip, "bob").is_err());
+        assert!(limits.check_login("10.0.0.2".parse().unwrap(), "bob").is_ok());
+    }
+
+    #[test]
+    fn success_clears_user_but_not_ip_failures() {
+        let limits = limits();
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        limits.login_failed(ip, "alice");
+        limits.login_succeeded("alice");
+        limits.login_failed(ip, "alice");
+        assert!(limits.check_login("10.0.0.2".parse().unwrap(), "alice").is_ok());
+        assert!(limits.check_login(ip, "bob").is_err());
+    }
+
+    #[test]
+    fn disabled_limits_allow_everything() {
+        let limits = RateLimits::new(&RateLimitConfig { enabled: false, ..Default::default() });
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        for _ in 0..100 {
+            limits.check_anonymous(ip, Some("alice")).unwrap();
+        }
+    }
+}
@@ -0,0 +1,297 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+use serde_json::Value;
+
+use super::connection::Connection;
+use crate::config::WebSocketConfig;
+use crate::state::AppState;
+
+// 会话在其他连接上恢复时关闭原连接
+pub const CLOSE_SESSION_RESUMED: u16 = 4003;
+
+const SWEEP_INTERVAL: Duration = Duration::from_secs(10);
+
+// 推送给会话的消息，恢复时按seq补发
+#[derive(Debug, Clone)]
+pub struct Pushed {
+    pub seq: u64,
+    pub method: String,
+    pub params: Value,
+}
+
+// 连接认证（login或升级时认证）后的会话，连接断开后保留一段时间，期间可在新连接上恢复
+#[derive(Debug)]
+pub struct Session {
+    pub resume_token: String,
+    pub user_id: String,
+    pub device_id: String,
+    capacity: usize,
+    state: Mutex<SessionState>,
+}
+
+#[derive(Debug)]
+struct SessionState {
+    // 恢复时重新校验（吊销、封禁），并作为新连接的token
+    token: String,
+    // 使用该会话的连接，断开后为None
+    conn_id: Option<u64>,
+    detached_at: Instant,
+    // 断开时订阅了设备状态变化，断开期间的推送继续缓存，恢复后重新订阅
+    presence: bool,
+    last_seq: u64,
+    pushed: VecDeque<Pushed>,
+}
+
+impl Session {
+    pub fn token(&self) -> String {
+        self.state.lock().unwrap().token.clone()
+    }
+
+    // 缓存一条推送，返回它的seq
+    pub fn record(&self, method: &str, params: &Value) -> u64 {
+        let mut state = self.state.lock().unwrap();
+        state.last_seq += 1;
+        let seq = state.last_seq;
+        state
+            .pushed
+            .push_back(Pushed { seq, method: method.to_owned(), params: params.clone() });
+        if state.pushed.len() > self.capacity {
+            state.pushed.pop_front();
+        }
+        seq
+    }
+
+    // last_seq之后缓存的推送；第二个值表示其中有推送已被丢弃
+    pub fn replay(&self, last_seq: u64) -> (Vec<Pushed>, bool) {
+        let state = self.state.lock().unwrap();
+        let oldest = state.pushed.front().map_or(state.last_seq + 1, |p| p.seq);
+        let pushed = state.pushed.iter().filter(|p| p.seq > last_seq).cloned().collect();
+        (pushed, last_seq + 1 < oldest)
+    }
+
+    // 绑定到新连接，返回之前仍绑定的连接，以及断开时是否订阅了设备状态变化
+    pub fn attach(&self, conn_id: u64) -> (Option<u64>, bool) {
+        let mut state = self.state.lock().unwrap();
+        let presence = std::mem::take(&mut state.presence);
+        (state.conn_id.replace(conn_id), presence)
+    }
+
+    fn detach(&self, conn_id: u64, presence: bool) {
+        let mut state = self.state.lock().unwrap();
+        // 已在其他连接上恢复
+        if state.conn_id != Some(conn_id) {
+            return;
+        }
+        state.conn_id = None;
+        state.detached_at = Instant::now();
+        state.presence = presence;
+    }
+
+    // 断开期间仍订阅设备状态变化
+    pub fn detached_presence(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        state.conn_id.is_none() && state.presence
+    }
+
+    fn expired(&self, grace: Duration) -> bool {
+        let state = self.state.lock().unwrap();
+        state.conn_id.is_none() && state.detached_at.elapsed() > grace
+    }
+}
+
+// 当前进程中的会话，以resume_token为key；会话不在节点之间共享
+#[derive(Debug)]
+pub struct Sessions {
+    inner: RwLock<HashMap<String, Arc<Session>>>,
+    grace: Duration,
+    capacity: usize,
+}
+
+impl Sessions {
+    pub fn new(config: &WebSocketConfig) -> Self {
+        Sessions {
+            inner: Default::default(),
+            grace: Duration::from_secs(config.resume_grace_secs),
+            capacity: config.resume_buffer,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        !self.grace.is_zero()
+    }
+
+    // 未过期的会话
+    pub fn get(&self, resume_token: &str) -> Option<Arc<Session>> {
+        let session = self.inner.read().unwrap().get(resume_token).cloned()?;
+        (!session.expired(self.grace)).then_some(session)
+    }
+
+    pub fn remove(&self, resume_token: &str) {
+        self.inner.write().unwrap().remove(resume_token);
+    }
+
+    pub fn all(&self) -> Vec<Arc<Session>> {
+        self.inner.read().unwrap().values().cloned().collect()
+    }
+
+    fn open(&self, conn_id: u64, user_id: &str, device_id: &str, token: &str) -> Arc<Session> {
+        let session = Arc::new(Session {
+            resume_token: hex::encode(rand::thread_rng().gen::<[u8; 24]>()),
+            user_id: user_id.to_owned(),
+            device_id: device_id.to_owned(),
+            capacity: self.capacity,
+            state: Mutex::new(SessionState {
+                token: token.to_owned(),
+                conn_id: Some(conn_id),
+                detached_at: Instant::now(),
+                presence: false,
+                last_seq: 0,
+                pushed: VecDeque::new(),
+            }),
+        });
+        self.inner
+            .write()
+            .unwrap()
+            .insert(session.resume_token.clone(), session.clone());
+        session
+    }
+
+    fn sweep(&self) {
+        self.inner.write().unwrap().retain(|_, s| !s.expired(self.grace));
+    }
+}
+
+// 连接认证后建立会话并返回resume_token；同一用户与设备再次认证时沿用已有会话
+pub fn start(state: &AppState, conn: &Connection) -> Option<String> {
+    if !state.sessions.enabled() {
+        return None;
+    }
+    let identity = conn.identity()?;
+    let token = conn.token()?;
+    if let Some(session) = conn.session() {
+        if session.user_id == identity.user_id && session.device_id == identity.device_id {
+            session.state.lock().unwrap().token = token;
+            return Some(session.resume_token.clone());
+        }
+        state.sessions.remove(&session.resume_token);
+    }
+    let session = state.sessions.open(conn.id, &identity.user_id, &identity.device_id, &token);
+    conn.set_session(Some(session.clone()));
+    Some(session.resume_token.clone())
+}
+
+// 连接断开后会话保留resume_grace_secs
+pub fn detach(conn: &Connection, presence: bool) {
+    if let Some(session) = conn.session() {
+        session.detach(conn.id, presence);
+    }
+}
+
+// 定期清理过期的会话
+pub async fn sweep(sessions: Arc<Sessions>) {
+    let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+    loop {
+        interval.tick().await;
+        sessions.sweep();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn sessions(grace: Duration, capacity: usize) -> Sessions {
+        Sessions { inner: Default::default(), grace, capacity }
+    }
+
+    fn session(capacity: usize) -> Arc<Session> {
+        sessions(Duration::from_secs(60), capacity).open(1, "alice", "1", "token")
+    }
+
+    fn seqs(pushed: &[Pushed]) -> Vec<u64> {
+        pushed.iter().map(|p| p.seq).collect()
+    }
+
+    #[test]
+    fn replay_empty_buffer() {
+        let session = session(2);
+        let (pushed, gap) = session.replay(0);
+        assert!(pushed.is_empty());
+        assert!(!gap);
+
+        // 不缓存推送时，客户端未收到最后一条即有遗漏
+        let session = self::session(0);
+        session.record("presence", &json!({}));
+        let (pushed, gap) = session.replay(0);
+        assert!(pushed.is_empty());
+        assert!(gap);
+        assert!(!session.replay(1).1);
+    }
+
+    #[test]
+    fn replay_after_last_seq() {
+        let session = session(2);
+        for _ in 0..3 {
+            session.record("presence", &json!({}));
+        }
+        // 缓存中为2、3，客户端已收到1，没有遗漏
+        let (pushed, gap) = session.replay(1);
+        assert_eq!(seqs(&pushed), vec![2, 3]);
+        assert!(!gap);
+        let (pushed, gap) = session.replay(3);
+        assert!(pushed.is_empty());
+        assert!(!gap);
+    }
+
+    #[test]
+    fn replay_reports_dropped() {
+        let session = session(2);
+        for _ in 0..4 {
+            session.record("presence", &json!({}));
+        }
+        // seq 2已被丢弃
+        let (pushed, gap) = session.replay(1);
+        assert_eq!(seqs(&pushed), vec![3, 4]);
+        assert!(gap);
+        let (_, gap) = session.replay(0);
+        assert!(gap);
+    }
+
+    #[test]
+    fn attach_takes_over() {
+        let session = session(2);
+        // 仍连接在1上时由2恢复，需关闭1
+        assert_eq!(session.attach(2), (Some(1), false));
+        // 1断开时会话已属于2，不影响会话
+        session.detach(1, true);
+        assert!(!session.detached_presence());
+
+        session.detach(2, true);
+        assert!(session.detached_presence());
+        assert_eq!(session.attach(3), (None, true));
+        assert!(!session.detached_presence());
+    }
+
+    #[test]
+    fn detached_sessions_expire() {
+        let sessions = sessions(Duration::from_millis(20), 2);
+        let session = sessions.open(1, "alice", "1", "token");
+        let token = session.resume_token.clone();
+        std::thread::sleep(Duration::from_millis(40));
+        // 连接中的会话不过期
+        assert!(sessions.get(&token).is_some());
+
+        session.detach(1, false);
+        assert!(sessions.get(&token).is_some());
+        std::thread::sleep(Duration::from_millis(40));
+        // 过期后不能再恢复，清理时删除
+        assert!(sessions.get(&token).is_none());
+        assert_eq!(sessions.all().len(), 1);
+        sessions.sweep();
+        assert!(sessions.all().is_empty());
+    }
+}
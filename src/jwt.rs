@@ -1,6 +1,7 @@
 use axum::async_trait;
-use axum::extract::{FromRequestParts, TypedHeader};
+use axum::extract::{FromRequestParts, Query, TypedHeader};
 use axum::headers::{authorization::Bearer, Authorization};
+use axum::http::header::SEC_WEBSOCKET_PROTOCOL;
 use axum::http::request::Parts;
 use axum::http::StatusCode;
 use axum::response::IntoResponse;
@@ -10,15 +11,37 @@ use serde::{Deserialize, Serialize};
 
 const SECRET: &[u8] = b"deadbeef";
 
+// token有效期，14天
+pub const TOKEN_LIFETIME_SECS: usize = 14 * 24 * 60 * 60;
+
+// 浏览器无法为WebSocket设置Authorization头，可在Sec-WebSocket-Protocol中以 "bearer.<token>" 携带
+pub const SUBPROTOCOL_PREFIX: &str = "bearer.";
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
     pub user_id: String,
     pub device_id: String,
     // Required. Expiration time (as UTC timestamp)
     pub exp: usize,
+    // 签发时间，用于判断token是否已被吊销；旧版本签发的token没有该字段
+    #[serde(default)]
+    pub iat: usize,
+    // 校验时填入的原始token，不编码进JWT
+    #[serde(skip)]
+    pub token: String,
 }
-pub fn verify_token(token: &str) -> bool {
-    true
+
+#[derive(Debug, Deserialize)]
+struct TokenQuery {
+    token: Option<String>,
+}
+
+// 校验token并解析出其中的Claims
+pub fn verify_token(token: &str) -> Result<Claims, HttpError> {
+    let key = jwt::DecodingKey::from_secret(SECRET);
+    jwt::decode::<Claims>(token, &key, &Validation::default())
+        .map(|data| Claims { token: token.to_owned(), ..data.claims })
+        .map_err(|_e| HttpError::Auth)
 }
 
 pub fn new_token(user_id: String, device_id: String) -> String {
@@ -26,11 +49,12 @@ pub fn new_token(user_id: String, device_id: String) -> String {
     let claims = Claims {
         user_id,
         device_id,
-        exp: get_epoch() + 14 * 24 * 60 * 60, // 14天后过期
+        exp: get_epoch() + TOKEN_LIFETIME_SECS,
+        iat: get_epoch(),
+        token: String::new(),
     };
     let key = jwt::EncodingKey::from_secret(SECRET);
-    let token = jwt::encode(&jwt::Header::default(), &claims, &key).unwrap();
-    token
+    jwt::encode(&jwt::Header::default(), &claims, &key).unwrap()
 }
 
 fn get_epoch() -> usize {
@@ -50,32 +74,66 @@ where
         state: &S,
     ) -> anyhow::Result<Self, Self::Rejection> {
         // 要求Axum使用features = ["headers"]
-        // 拿到bear token
-        let TypedHeader(Authorization(bearer)) =
-            TypedHeader::<Authorization<Bearer>>::from_request_parts(parts, state)
-                .await
-                .map_err(|_| HttpError::Auth)?;
-        let key = jwt::DecodingKey::from_secret(SECRET);
+        // 依次从Authorization头、Sec-WebSocket-Protocol与查询参数token中拿到bear token
+        let token =
+            match TypedHeader::<Authorization<Bearer>>::from_request_parts(parts, state).await {
+                Ok(TypedHeader(Authorization(bearer))) => bearer.token().to_owned(),
+                Err(_) => match protocol_token(parts) {
+                    Some(token) => token,
+                    None => Query::<TokenQuery>::from_request_parts(parts, state)
+                        .await
+                        .ok()
+                        .and_then(|Query(query)| query.token)
+                        .ok_or(HttpError::MissingToken)?,
+                },
+            };
         // Decode bear token
-        let token = jwt::decode::<Claims>(bearer.token(), &key, &Validation::default())
-            .map_err(|_e| HttpError::Auth)?;
-        Ok(token.claims)
+        verify_token(&token)
     }
 }
 
+fn protocol_token(parts: &Parts) -> Option<String> {
+    parts
+        .headers
+        .get_all(SEC_WEBSOCKET_PROTOCOL)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .find_map(|p| p.trim().strip_prefix(SUBPROTOCOL_PREFIX))
+        .map(str::to_owned)
+}
+
 #[derive(Debug)]
 pub enum HttpError {
     Auth,
+    // 请求中没有token，允许匿名访问时可忽略
+    MissingToken,
     Internal,
+    NotFound(String),
+    BadRequest(String),
 }
 
 impl IntoResponse for HttpError {
     fn into_response(self) -> axum::response::Response {
         let (code, msg) = match self {
-            HttpError::Auth => (StatusCode::UNAUTHORIZED, "Unauthorized"),
-            HttpError::Internal => (StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error"),
+            HttpError::Auth | HttpError::MissingToken => {
+                (StatusCode::UNAUTHORIZED, "Unauthorized".to_owned())
+            }
+            HttpError::Internal => {
+                (StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error".to_owned())
+            }
+            HttpError::NotFound(what) => (StatusCode::NOT_FOUND, format!("Not found: {}", what)),
+            HttpError::BadRequest(e) => (StatusCode::BAD_REQUEST, e),
         };
         // Axum已经实现的如果tupe每个字段都实现了into_response，那这个tupe也会实现into_response
         (code, msg).into_response()
     }
 }
+
+// 内部错误细节只记录日志，不返回给客户端
+impl From<anyhow::Error> for HttpError {
+    fn from(e: anyhow::Error) -> Self {
+        tracing::event!(tracing::Level::ERROR, "Internal error: {:?}", e);
+        HttpError::Internal
+    }
+}
@@ -1,40 +1,186 @@
+use axum::http::header;
 use axum::routing::get;
 use axum::Router;
-use tower_http::trace::{DefaultMakeSpan, TraceLayer};
+use tower_http::sensitive_headers::SetSensitiveRequestHeadersLayer;
+use tower_http::trace::TraceLayer;
 
 use std::net::SocketAddr;
+use std::time::Duration;
 
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
-
+mod bus;
+mod cli;
+mod config;
 mod db;
 mod handler;
 mod jwt;
+mod metrics;
+mod schema;
+mod shutdown;
+mod state;
+mod telemetry;
+mod tls;
 mod types;
 mod utils;
 
 #[tokio::main]
 async fn main() {
-    tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "deeplink_rs=debug,tower_http=debug".into()),
-        )
-        .with(tracing_subscriber::fmt::layer())
-        .init();
-
-    let db = db::DB::new().await;
-    let app = Router::new()
-        .route("/ws", get(handler::handlers::ws_handler).with_state(db))
+    let cli = match cli::Cli::parse() {
+        Ok(cli) => cli,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+    // 导出协议文档不需要配置
+    if let cli::Command::Schema { check } = &cli.command {
+        match check {
+            Some(path) => {
+                if let Err(e) = schema::check(path) {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            }
+            None => print!("{}", schema::render()),
+        }
+        return;
+    }
+    // 日志按配置初始化，这之前的错误只能输出到stderr
+    let config = match config::Config::load(cli.config.as_deref()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{:?}", e);
+            std::process::exit(-1);
+        }
+    };
+    let _telemetry = match telemetry::init(&config.log) {
+        Ok(telemetry) => telemetry,
+        Err(e) => {
+            eprintln!("Init logging failed: {:?}", e);
+            std::process::exit(-1);
+        }
+    };
+
+    if let cli::Command::Migrate { dry_run } = cli.command {
+        match db::DB::migrate(&config, dry_run).await {
+            Ok(outcomes) => outcomes.iter().for_each(|outcome| println!("{}", outcome)),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "Migrate failed: {:?}", e);
+                std::process::exit(-1);
+            }
+        }
+        return;
+    }
+
+    let db = db::DB::new(&config).await;
+    let bus = match bus::Bus::new(&config.bus).await {
+        Ok(bus) => bus,
+        Err(e) => {
+            tracing::event!(tracing::Level::ERROR, "Init message bus failed: {:?}", e);
+            std::process::exit(-1);
+        }
+    };
+    let bus_rx = match bus.subscribe() {
+        Ok(rx) => rx,
+        Err(e) => {
+            tracing::event!(tracing::Level::ERROR, "Subscribe message bus failed: {:?}", e);
+            std::process::exit(-1);
+        }
+    };
+    tracing::event!(tracing::Level::INFO, "Node {}", bus.node_id());
+    // 固定node_id时清理上次运行未正常关闭遗留的连接记录
+    if config.bus.node_id.is_some() {
+        match db.delete_node_connections(bus.node_id()).await {
+            Ok(0) => {}
+            Ok(n) => {
+                tracing::event!(tracing::Level::INFO, "Removed {} stale connection records", n)
+            }
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "Remove stale connections failed: {:?}", e);
+                std::process::exit(-1);
+            }
+        }
+    }
+    let state = state::AppState::new(config, db, bus);
+    tokio::spawn(handler::cluster::run(state.clone(), bus_rx));
+    tokio::spawn(handler::cluster::heartbeat(state.clone()));
+    if let Err(e) = handler::ban::reload(&state).await {
+        tracing::event!(tracing::Level::ERROR, "Load bans failed: {:?}", e);
+        std::process::exit(-1);
+    }
+    tokio::spawn(handler::ban::watch(state.clone()));
+    tokio::spawn(handler::ratelimit::sweep(state.limits.clone()));
+    tokio::spawn(handler::session::sweep(state.sessions.clone()));
+    if state.config.audit.enabled && state.config.audit.retention_days > 0 {
+        tokio::spawn(handler::audit::prune(state.clone()));
+    }
+    let mut app = Router::new()
+        .route("/ws", get(handler::handlers::ws_handler))
+        .route("/healthz", get(handler::health::healthz))
+        .route("/readyz", get(handler::health::readyz))
+        .route("/version", get(handler::health::version))
+        .route("/metrics", get(metrics::metrics));
+    // 配置了管理凭证时才提供管理接口
+    if state.config.admin.token.is_some() {
+        app = app.nest("/admin", handler::admin::router());
+    }
+    let app = app
+        .with_state(state.clone())
         // logging so we can see whats going on
-        .layer(
-            TraceLayer::new_for_http()
-                .make_span_with(DefaultMakeSpan::default().include_headers(true)),
-        );
+        .layer(TraceLayer::new_for_http().make_span_with(telemetry::http_span))
+        // 在TraceLayer之前执行：token等凭证在日志与导出的span中显示为Sensitive
+        // Sec-WebSocket-Protocol中可能带有 "bearer.<token>"
+        .layer(SetSensitiveRequestHeadersLayer::new([
+            header::AUTHORIZATION,
+            header::COOKIE,
+            header::SEC_WEBSOCKET_PROTOCOL,
+        ]));
+
+    let listen = state.config.listen;
+    let app = app.into_make_service_with_connect_info::<SocketAddr>();
+    let shutdown_timeout = Duration::from_secs(state.config.shutdown_timeout_secs);
+    let Some(tls_config) = state.config.tls.clone() else {
+        tracing::event!(tracing::Level::INFO, "Listening on {}", listen);
+        let (drained_tx, drained_rx) = tokio::sync::oneshot::channel();
+        let server = axum::Server::bind(&listen).serve(app).with_graceful_shutdown(async move {
+            shutdown::graceful_shutdown(state).await;
+            let _ = drained_tx.send(());
+        });
+        tokio::pin!(server);
+        tokio::select! {
+            result = &mut server => result.unwrap(),
+            // WebSocket连接关闭后，剩余的HTTP请求最多再等待shutdown_timeout_secs
+            Ok(()) = drained_rx => match tokio::time::timeout(shutdown_timeout, server).await {
+                Ok(result) => result.unwrap(),
+                Err(_) => tracing::event!(
+                    tracing::Level::WARN,
+                    "HTTP requests still running after {:?}, exiting",
+                    shutdown_timeout
+                ),
+            },
+        }
+        return;
+    };
 
-    let addr = SocketAddr::from(([0, 0, 0, 0], 3000));
+    let rustls = match tls::load(&tls_config).await {
+        Ok(rustls) => rustls,
+        Err(e) => {
+            tracing::event!(tracing::Level::ERROR, "{:?}", e);
+            std::process::exit(-1);
+        }
+    };
+    tokio::spawn(tls::watch(rustls.clone(), tls_config));
 
-    axum::Server::bind(&addr)
-        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+    let handle = axum_server::Handle::new();
+    let shutdown_handle = handle.clone();
+    tokio::spawn(async move {
+        shutdown::graceful_shutdown(state).await;
+        // WebSocket连接关闭后，剩余的HTTP请求最多再等待shutdown_timeout_secs
+        shutdown_handle.graceful_shutdown(Some(shutdown_timeout));
+    });
+    tracing::event!(tracing::Level::INFO, "Listening on {} (TLS)", listen);
+    axum_server::bind_rustls(listen, rustls)
+        .handle(handle)
+        .serve(app)
         .await
         .unwrap();
 }
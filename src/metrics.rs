@@ -0,0 +1,104 @@
+use std::time::Duration;
+
+use axum::extract::State;
+use axum::http::{header, StatusCode};
+use axum::response::IntoResponse;
+use once_cell::sync::Lazy;
+use prometheus::{
+    register_histogram_vec, register_int_counter_vec, register_int_gauge, Encoder, HistogramVec,
+    IntCounterVec, IntGauge, TextEncoder,
+};
+
+use crate::handler::RpcError;
+use crate::state::AppState;
+
+pub static WS_CONNECTIONS: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!("deeplink_ws_connections", "Active WebSocket connections").unwrap()
+});
+
+pub static DEVICES_ONLINE: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!("deeplink_devices_online", "Devices with an authenticated connection")
+        .unwrap()
+});
+
+pub static REQUESTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!("deeplink_requests_total", "Requests by method", &["method"]).unwrap()
+});
+
+pub static REQUEST_ERRORS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "deeplink_request_errors_total",
+        "Failed requests by method and error code",
+        &["method", "code"]
+    )
+    .unwrap()
+});
+
+pub static REQUEST_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "deeplink_request_duration_seconds",
+        "Request handling latency by method",
+        &["method"]
+    )
+    .unwrap()
+});
+
+pub static LOGINS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!("deeplink_logins_total", "Login attempts by result", &["result"])
+        .unwrap()
+});
+
+pub static RATE_LIMITED: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "deeplink_rate_limited_total",
+        "Rejected requests by limit (connection, ip, user, lockout)",
+        &["scope"]
+    )
+    .unwrap()
+});
+
+pub static STORAGE_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "deeplink_storage_duration_seconds",
+        "Storage operation latency",
+        &["backend", "operation", "result"]
+    )
+    .unwrap()
+});
+
+// 记录一次请求，未知的method统一记为"unknown"，避免客户端随意制造label
+pub fn observe_request(
+    method: &str,
+    known: bool,
+    elapsed: Duration,
+    result: Result<(), &RpcError>,
+) {
+    let method = if known { method } else { "unknown" };
+    REQUESTS.with_label_values(&[method]).inc();
+    REQUEST_DURATION.with_label_values(&[method]).observe(elapsed.as_secs_f64());
+    if let Err(e) = result {
+        REQUEST_ERRORS.with_label_values(&[method, &e.code().to_string()]).inc();
+    }
+}
+
+pub fn observe_login(result: Result<(), &RpcError>) {
+    let reason = match result {
+        Ok(()) => "success",
+        Err(RpcError::InvalidNonce) => "invalid_nonce",
+        Err(RpcError::InvalidSignature) => "invalid_signature",
+        Err(RpcError::InvalidParams(_)) => "invalid_params",
+        Err(_) => "error",
+    };
+    LOGINS.with_label_values(&[reason]).inc();
+}
+
+// GET /metrics，Prometheus文本格式
+pub async fn metrics(State(state): State<AppState>) -> impl IntoResponse {
+    DEVICES_ONLINE.set(state.connections.online_devices() as i64);
+
+    let mut buffer = vec![];
+    if let Err(e) = TextEncoder::new().encode(&prometheus::gather(), &mut buffer) {
+        return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+    }
+    ([(header::CONTENT_TYPE, prometheus::TEXT_FORMAT)], buffer).into_response()
+}
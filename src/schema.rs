@@ -0,0 +1,282 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use schemars::gen::{SchemaGenerator, SchemaSettings};
+use schemars::schema::Schema;
+use schemars::JsonSchema;
+use serde_json::{json, Value};
+
+use crate::handler::protocol::PROTOCOL_VERSION;
+use crate::types::{
+    BindDeviceParams, CreateGroupParams, DeviceIdParams, DeviceListResult, GetDeviceListParams,
+    GetNonceParams, GroupDevicesParams, GroupIdParams, GroupListResult, GroupResult, HelloParams,
+    HelloResult, LoginParams, LoginResult, MessageResult, PresenceEvent, RegisterDeviceParams,
+    RegisterDeviceResult, RequestMethod, RequestParams, ResponseParams, ResumeParams, ResumeResult,
+    SetDeviceTagsParams, ShareGroupParams, UpdateGroupParams, UserNonceResult,
+};
+
+const OPENRPC_VERSION: &str = "1.2.6";
+
+// 方法是否需要token
+#[derive(Debug, Clone, Copy)]
+enum Auth {
+    None,
+    Optional,
+    Required,
+}
+
+struct MethodSpec {
+    summary: &'static str,
+    auth: Auth,
+    // 参数对象的schema，无参数时为None
+    params: Option<Schema>,
+    result: Schema,
+}
+
+// 新增方法时这里的match不完整会编译失败，保证文档覆盖所有方法
+fn spec(method: RequestMethod, gen: &mut SchemaGenerator) -> MethodSpec {
+    let (summary, auth, params, result) = match method {
+        RequestMethod::Hello => (
+            "Declare the client version and get the server version, methods and features",
+            Auth::None,
+            Some(HelloParams::json_schema(gen)),
+            gen.subschema_for::<HelloResult>(),
+        ),
+        RequestMethod::GetNonce => (
+            "Get the last used login nonce of a user",
+            Auth::None,
+            Some(GetNonceParams::json_schema(gen)),
+            gen.subschema_for::<UserNonceResult>(),
+        ),
+        RequestMethod::RegisterDevice => (
+            "Register a device, or reclaim a device bound to the token's user",
+            Auth::Optional,
+            Some(RegisterDeviceParams::json_schema(gen)),
+            gen.subschema_for::<RegisterDeviceResult>(),
+        ),
+        RequestMethod::Login => (
+            "Log in with a signed nonce and get a token",
+            Auth::None,
+            Some(LoginParams::json_schema(gen)),
+            gen.subschema_for::<LoginResult>(),
+        ),
+        RequestMethod::Resume => (
+            "Resume a session after reconnecting and replay missed pushes",
+            Auth::None,
+            Some(ResumeParams::json_schema(gen)),
+            gen.subschema_for::<ResumeResult>(),
+        ),
+        RequestMethod::BindDevice => (
+            "Bind the token's device to the user",
+            Auth::Required,
+            Some(BindDeviceParams::json_schema(gen)),
+            gen.subschema_for::<MessageResult>(),
+        ),
+        RequestMethod::UnbindDevice => (
+            "Unbind a device from the user",
+            Auth::Required,
+            Some(DeviceIdParams::json_schema(gen)),
+            gen.subschema_for::<MessageResult>(),
+        ),
+        RequestMethod::GetDeviceList => (
+            "List the user's devices, optionally filtered by group or tag",
+            Auth::Required,
+            Some(GetDeviceListParams::json_schema(gen)),
+            gen.subschema_for::<DeviceListResult>(),
+        ),
+        RequestMethod::SetDeviceTags => (
+            "Replace the tags of a bound device",
+            Auth::Required,
+            Some(SetDeviceTagsParams::json_schema(gen)),
+            gen.subschema_for::<MessageResult>(),
+        ),
+        RequestMethod::CreateGroup => (
+            "Create a device group",
+            Auth::Required,
+            Some(CreateGroupParams::json_schema(gen)),
+            gen.subschema_for::<GroupResult>(),
+        ),
+        RequestMethod::UpdateGroup => (
+            "Rename a device group",
+            Auth::Required,
+            Some(UpdateGroupParams::json_schema(gen)),
+            gen.subschema_for::<GroupResult>(),
+        ),
+        RequestMethod::DeleteGroup => (
+            "Delete a device group",
+            Auth::Required,
+            Some(GroupIdParams::json_schema(gen)),
+            gen.subschema_for::<MessageResult>(),
+        ),
+        RequestMethod::GetGroupList => (
+            "List owned groups and groups shared with the user",
+            Auth::Required,
+            None,
+            gen.subschema_for::<GroupListResult>(),
+        ),
+        RequestMethod::AddGroupDevices => (
+            "Add bound devices to a group",
+            Auth::Required,
+            Some(GroupDevicesParams::json_schema(gen)),
+            gen.subschema_for::<GroupResult>(),
+        ),
+        RequestMethod::RemoveGroupDevices => (
+            "Remove devices from a group",
+            Auth::Required,
+            Some(GroupDevicesParams::json_schema(gen)),
+            gen.subschema_for::<GroupResult>(),
+        ),
+        RequestMethod::ShareGroup => (
+            "Share a group with another user",
+            Auth::Required,
+            Some(ShareGroupParams::json_schema(gen)),
+            gen.subschema_for::<GroupResult>(),
+        ),
+        RequestMethod::UnshareGroup => (
+            "Stop sharing a group with a user",
+            Auth::Required,
+            Some(ShareGroupParams::json_schema(gen)),
+            gen.subschema_for::<GroupResult>(),
+        ),
+        RequestMethod::SubscribePresence => (
+            "Receive presence events for the devices visible to the user",
+            Auth::Required,
+            None,
+            gen.subschema_for::<MessageResult>(),
+        ),
+        RequestMethod::UnsubscribePresence => (
+            "Stop receiving presence events",
+            Auth::None,
+            None,
+            gen.subschema_for::<MessageResult>(),
+        ),
+    };
+    MethodSpec { summary, auth, params, result }
+}
+
+// 参数对象的每个字段为一个按名称传递的参数，token放在params.token中
+fn content_descriptors(params: Option<Schema>, auth: Auth) -> Vec<Value> {
+    let object = match params {
+        Some(Schema::Object(schema)) => schema.object.map(|o| *o).unwrap_or_default(),
+        _ => Default::default(),
+    };
+    let mut descriptors: Vec<Value> = object
+        .properties
+        .into_iter()
+        .map(|(name, schema)| {
+            let required = object.required.contains(&name);
+            json!({ "name": name, "required": required, "schema": schema })
+        })
+        .collect();
+    // 未提供时使用连接最近一次登录的token，因此参数本身总是可选的
+    let token = |summary: &str| {
+        json!({
+            "name": "token",
+            "summary": summary,
+            "required": false,
+            "schema": { "type": "string" },
+        })
+    };
+    match auth {
+        Auth::None => {}
+        Auth::Optional => {
+            descriptors.push(token("Defaults to the token of the connection's last login"))
+        }
+        Auth::Required => descriptors.push(token(
+            "Defaults to the token of the connection's last login; \
+             the call fails if neither is available",
+        )),
+    }
+    descriptors
+}
+
+// 所有方法的OpenRPC文档，组件中另外包含非JSON-RPC格式的请求与响应，以及推送的消息
+pub fn openrpc() -> Value {
+    let mut gen = SchemaSettings::draft07()
+        .with(|s| s.definitions_path = "#/components/schemas/".to_owned())
+        .into_generator();
+    let methods: Vec<Value> = RequestMethod::ALL
+        .into_iter()
+        .map(|method| {
+            let spec = spec(method, &mut gen);
+            json!({
+                "name": method.as_str(),
+                "summary": spec.summary,
+                "paramStructure": "by-name",
+                "params": content_descriptors(spec.params, spec.auth),
+                "result": { "name": format!("{}Result", method.as_str()), "schema": spec.result },
+            })
+        })
+        .collect();
+    gen.subschema_for::<RequestParams>();
+    gen.subschema_for::<ResponseParams<Value>>();
+    gen.subschema_for::<PresenceEvent>();
+
+    json!({
+        "openrpc": OPENRPC_VERSION,
+        "info": {
+            "title": env!("CARGO_PKG_NAME"),
+            // 文档的版本为协议版本，服务端版本变化但协议不变时文档不变
+            "version": PROTOCOL_VERSION.to_string(),
+        },
+        "methods": methods,
+        "components": { "schemas": gen.definitions() },
+    })
+}
+
+pub fn render() -> String {
+    // Value的序列化不会失败
+    serde_json::to_string_pretty(&openrpc()).unwrap() + "\n"
+}
+
+// 与已提交的文档比较，不一致说明协议格式有变化，需要检查后重新生成
+pub fn check(path: &Path) -> Result<()> {
+    let expected = std::fs::read_to_string(path)
+        .map_err(|e| anyhow!("Read {} failed: {}", path.display(), e))?;
+    if expected != render() {
+        return Err(anyhow!(
+            "{} is out of date, review the protocol change and run `deeplink-rs schema > {}`",
+            path.display(),
+            path.display()
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 修改了协议相关的类型或说明后需重新生成schema/openrpc.json
+    #[test]
+    fn committed_schema_is_up_to_date() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("schema/openrpc.json");
+        check(&path).unwrap();
+    }
+
+    #[test]
+    fn documents_every_method() {
+        let document: Value = serde_json::from_str(&render()).unwrap();
+        let names: Vec<&str> = document["methods"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|m| m["name"].as_str().unwrap())
+            .collect();
+        let expected: Vec<&str> = RequestMethod::ALL.iter().map(|m| m.as_str()).collect();
+        assert_eq!(names, expected);
+    }
+
+    // token可以从连接最近一次登录中获得，任何方法都不要求显式传入
+    #[test]
+    fn token_param_is_optional() {
+        let document: Value = serde_json::from_str(&render()).unwrap();
+        for method in document["methods"].as_array().unwrap() {
+            for param in method["params"].as_array().unwrap() {
+                if param["name"] == "token" {
+                    assert_eq!(param["required"], false, "{}", method["name"]);
+                }
+            }
+        }
+    }
+}
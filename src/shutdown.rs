@@ -0,0 +1,75 @@
+use std::collections::HashSet;
+use std::time::Duration;
+
+use tracing::Level;
+
+use crate::handler::{cluster, presence};
+use crate::state::AppState;
+use crate::types::PresenceChange;
+
+// 1012: Service Restart
+const CLOSE_SERVICE_RESTART: u16 = 1012;
+
+// 传给axum::Server::with_graceful_shutdown：收到信号后先关闭所有WebSocket连接，
+// 期间HTTP服务仍在运行（/readyz返回503，拒绝新的WebSocket连接），完成后再停止服务
+pub async fn graceful_shutdown(state: AppState) {
+    signal(&state).await;
+    drain(&state, Duration::from_secs(state.config.shutdown_timeout_secs)).await;
+}
+
+// 等待SIGINT(Ctrl-C)或SIGTERM，收到后进入draining状态
+async fn signal(state: &AppState) {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c().await.expect("failed to install Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+    tracing::event!(Level::INFO, "Shutdown signal received, draining connections");
+    state.start_draining();
+}
+
+// 通知所有连接服务即将重启，等待连接关闭（最多deadline），
+// 最后对仍未断开的连接执行与正常断开相同的清理
+async fn drain(state: &AppState, deadline: Duration) {
+    for conn in state.connections.all() {
+        conn.close(CLOSE_SERVICE_RESTART, "server restarting, reconnect");
+    }
+
+    if tokio::time::timeout(deadline, state.connections.wait_empty()).await.is_err() {
+        tracing::event!(
+            Level::WARN,
+            "{} connections still open after {:?}",
+            state.connections.len(),
+            deadline
+        );
+    }
+
+    // 删除集群中的连接记录，将设备标记为离线并通知能看到该设备的用户
+    let mut offline = HashSet::new();
+    for conn in state.connections.all() {
+        cluster::unregister(state, &conn).await;
+        let Some(identity) = conn.identity() else {
+            continue;
+        };
+        if !offline.insert(identity.device_id.clone()) {
+            continue;
+        }
+        if let Err(e) = state.db.set_device_online(&identity.device_id, false).await {
+            tracing::event!(Level::ERROR, "Set device offline failed: {:?}", e);
+        }
+        presence::publish(state, &identity.device_id, PresenceChange::Offline).await;
+    }
+}
@@ -0,0 +1,56 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::bus::Bus;
+use crate::config::Config;
+use crate::db::DB;
+use crate::handler::ban::Bans;
+use crate::handler::connection::Connections;
+use crate::handler::presence::Presence;
+use crate::handler::ratelimit::RateLimits;
+use crate::handler::session::Sessions;
+
+// axum handler共享的服务状态
+#[derive(Clone)]
+pub struct AppState {
+    pub config: Arc<Config>,
+    pub db: DB,
+    pub bus: Bus,
+    pub connections: Connections,
+    pub limits: Arc<RateLimits>,
+    // 封禁列表缓存，启动时由ban::reload加载
+    pub bans: Arc<Bans>,
+    // 订阅了设备状态变化的连接
+    pub presence: Arc<Presence>,
+    // 可在断线重连后恢复的会话
+    pub sessions: Arc<Sessions>,
+    // 正在关闭服务，不再接受新的WebSocket连接
+    draining: Arc<AtomicBool>,
+}
+
+impl AppState {
+    pub fn new(config: Config, db: DB, bus: Bus) -> Self {
+        let connections = Connections::new(&config.websocket);
+        let limits = Arc::new(RateLimits::new(&config.rate_limit));
+        let sessions = Arc::new(Sessions::new(&config.websocket));
+        AppState {
+            config: Arc::new(config),
+            db,
+            bus,
+            connections,
+            limits,
+            bans: Default::default(),
+            presence: Default::default(),
+            sessions,
+            draining: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::SeqCst)
+    }
+
+    pub fn start_draining(&self) {
+        self.draining.store(true, Ordering::SeqCst);
+    }
+}
@@ -0,0 +1,73 @@
+use anyhow::Result;
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{trace::SdkTracerProvider, Resource};
+use tracing::Level;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+
+use crate::config::{LogConfig, LogFormat};
+
+// 日志与trace导出，drop时将未导出的span发送出去
+pub struct Telemetry {
+    provider: Option<SdkTracerProvider>,
+}
+
+pub fn init(config: &LogConfig) -> Result<Telemetry> {
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| config.filter.as_str().into());
+
+    let (text, json) = match config.format {
+        LogFormat::Text => (Some(tracing_subscriber::fmt::layer()), None),
+        LogFormat::Json => (None, Some(tracing_subscriber::fmt::layer().json())),
+    };
+
+    let provider = match &config.otlp_endpoint {
+        Some(endpoint) => {
+            let exporter = SpanExporter::builder().with_http().with_endpoint(endpoint).build()?;
+            Some(
+                SdkTracerProvider::builder()
+                    .with_batch_exporter(exporter)
+                    .with_resource(
+                        Resource::builder().with_service_name(env!("CARGO_PKG_NAME")).build(),
+                    )
+                    .build(),
+            )
+        }
+        None => None,
+    };
+    let otel = provider
+        .as_ref()
+        .map(|p| tracing_opentelemetry::layer().with_tracer(p.tracer(env!("CARGO_PKG_NAME"))));
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(text)
+        .with(json)
+        .with(otel)
+        .try_init()?;
+    if let Some(endpoint) = &config.otlp_endpoint {
+        tracing::event!(Level::INFO, "Exporting traces to {}", endpoint);
+    }
+    Ok(Telemetry { provider })
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        if let Some(provider) = self.provider.take() {
+            if let Err(e) = provider.shutdown() {
+                eprintln!("Shutdown trace exporter failed: {:?}", e);
+            }
+        }
+    }
+}
+
+// HTTP请求的span，与DefaultMakeSpan相同但uri只记录路径：?token=等查询参数可能带有凭证
+pub fn http_span<B>(request: &axum::http::Request<B>) -> tracing::Span {
+    tracing::debug_span!(
+        "request",
+        method = %request.method(),
+        uri = %request.uri().path(),
+        version = ?request.version(),
+        headers = ?request.headers(),
+    )
+}
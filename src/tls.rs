@@ -0,0 +1,64 @@
+use std::time::{Duration, SystemTime};
+
+use anyhow::{anyhow, Result};
+use axum_server::tls_rustls::RustlsConfig;
+use tracing::Level;
+
+use crate::config::TlsConfig;
+
+pub async fn load(config: &TlsConfig) -> Result<RustlsConfig> {
+    RustlsConfig::from_pem_file(&config.cert, &config.key).await.map_err(|e| {
+        anyhow!(
+            "Load TLS certificate {} / key {} failed: {}",
+            config.cert.display(),
+            config.key.display(),
+            e
+        )
+    })
+}
+
+// 证书文件变化或收到SIGHUP时重新加载证书，只影响之后的TLS握手，已建立的连接不受影响；
+// 加载失败时继续使用原来的证书
+pub async fn watch(rustls: RustlsConfig, config: TlsConfig) {
+    let mut interval = tokio::time::interval(Duration::from_secs(config.reload_interval_secs));
+    let mut last_modified = modified(&config);
+
+    #[cfg(unix)]
+    let mut hangup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())
+        .expect("failed to install SIGHUP handler");
+
+    loop {
+        #[cfg(unix)]
+        let hangup = hangup.recv();
+        #[cfg(not(unix))]
+        let hangup = std::future::pending::<Option<()>>();
+
+        let reason = tokio::select! {
+            _ = interval.tick() => {
+                let current = modified(&config);
+                if current == last_modified {
+                    continue;
+                }
+                last_modified = current;
+                "certificate changed"
+            },
+            _ = hangup => "SIGHUP received",
+        };
+
+        match rustls.reload_from_pem_file(&config.cert, &config.key).await {
+            Ok(()) => tracing::event!(Level::INFO, "Reloaded TLS certificate ({})", reason),
+            Err(e) => tracing::event!(
+                Level::ERROR,
+                "Reload TLS certificate failed, keeping the current one: {:?}",
+                e
+            ),
+        }
+    }
+}
+
+// 证书与私钥文件的修改时间
+fn modified(config: &TlsConfig) -> Option<(SystemTime, SystemTime)> {
+    let cert = std::fs::metadata(&config.cert).and_then(|m| m.modified()).ok()?;
+    let key = std::fs::metadata(&config.key).and_then(|m| m.modified()).ok()?;
+    Some((cert, key))
+}
@@ -1,21 +1,88 @@
 use anyhow::{anyhow, Result};
-use serde::{Deserialize, Serialize};
+use schemars::gen::SchemaGenerator;
+use schemars::schema::{InstanceType, Schema, SchemaObject};
+use schemars::JsonSchema;
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
 use serde_json::Value;
 use std::fmt::Debug;
 
-#[derive(Debug, Serialize, Deserialize)]
+// UTC时间戳：存入MongoDB时为BSON datetime，JSON中为RFC 3339字符串
+// 写入MongoDB时需使用非human readable的序列化（见db::to_bson）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Timestamp(pub bson::DateTime);
+
+impl Timestamp {
+    pub fn now() -> Self {
+        Timestamp(bson::DateTime::now())
+    }
+}
+
+// 默认值为当前时间
+impl Default for Timestamp {
+    fn default() -> Self {
+        Self::now()
+    }
+}
+
+impl From<Timestamp> for bson::Bson {
+    fn from(t: Timestamp) -> Self {
+        bson::Bson::DateTime(t.0)
+    }
+}
+
+impl Serialize for Timestamp {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            let s = self.0.try_to_rfc3339_string().map_err(serde::ser::Error::custom)?;
+            serializer.serialize_str(&s)
+        } else {
+            self.0.serialize(serializer)
+        }
+    }
+}
+
+// 描述JSON中的RFC 3339字符串
+impl JsonSchema for Timestamp {
+    fn schema_name() -> String {
+        "Timestamp".to_owned()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        SchemaObject {
+            instance_type: Some(InstanceType::String.into()),
+            format: Some("date-time".to_owned()),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
+impl<'de> Deserialize<'de> for Timestamp {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            let s = String::deserialize(deserializer)?;
+            bson::DateTime::parse_rfc3339_str(s).map(Timestamp).map_err(D::Error::custom)
+        } else {
+            bson::DateTime::deserialize(deserializer).map(Timestamp)
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct GetNonceParams {
     pub user_id: String,
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct DeviceInfo {
     pub device_id: String,
     pub device_name: String,
     pub mac: String,
+    #[serde(default)]
+    pub fingerprint: String,
     pub online: bool,
-    pub add_time: String,
-    pub update_time: String,
+    pub add_time: Timestamp,
+    pub update_time: Timestamp,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -24,30 +91,283 @@ pub struct UserNonce {
     pub nonce: String,
 }
 
+// 用户与设备的绑定关系
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceBinding {
+    pub user_id: String,
+    pub device_id: String,
+    pub device_name: String,
+    // 每个用户为自己绑定的设备设置的标签，互不影响
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+// 用户自定义的设备分组，可整组分享给其他用户
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct DeviceGroup {
+    pub group_id: String,
+    pub user_id: String,
+    pub name: String,
+    #[serde(default)]
+    pub device_ids: Vec<String>,
+    #[serde(default)]
+    pub shared_with: Vec<String>,
+}
+
+// 封禁对象的类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BanKind {
+    User,
+    Device,
+    Ip,
+}
+
+impl BanKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BanKind::User => "user",
+            BanKind::Device => "device",
+            BanKind::Ip => "ip",
+        }
+    }
+}
+
+impl TryFrom<&str> for BanKind {
+    type Error = anyhow::Error;
+
+    fn try_from(kind: &str) -> Result<Self> {
+        match kind {
+            "user" => Ok(BanKind::User),
+            "device" => Ok(BanKind::Device),
+            "ip" => Ok(BanKind::Ip),
+            _ => Err(anyhow!("Invalid ban kind: {}", kind)),
+        }
+    }
+}
+
+// 封禁记录，expires_at为空表示永久封禁
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Ban {
+    pub kind: BanKind,
+    pub value: String,
+    pub reason: String,
+    pub created_at: Timestamp,
+    pub expires_at: Option<Timestamp>,
+}
+
+impl Ban {
+    pub fn is_active(&self) -> bool {
+        self.expires_at.is_none_or(|t| t > Timestamp::now())
+    }
+}
+
+// 用户token吊销记录，revoked_before之前签发的token失效
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenRevocation {
+    pub user_id: String,
+    pub revoked_before: Timestamp,
+}
+
+// 已认证的连接所在的节点，用于把发给该用户的消息路由到对应节点
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConnectionRecord {
+    pub node_id: String,
+    pub conn_id: u64,
+    pub user_id: String,
+    pub device_id: String,
+    pub addr: String,
+    pub connected_at: Timestamp,
+    // 所在节点最近一次刷新记录的时间
+    #[serde(default)]
+    pub heartbeat_at: Timestamp,
+}
+
+// 审计事件类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    Login,
+    LoginFailed,
+    TokenIssued,
+    TokenRevoked,
+    DeviceRegistered,
+    DeviceBound,
+    DeviceUnbound,
+    GroupShared,
+    GroupUnshared,
+    SessionStarted,
+    SessionEnded,
+    SessionResumed,
+    AdminDisconnect,
+    AdminBan,
+    AdminUnban,
+}
+
+impl AuditAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::Login => "login",
+            AuditAction::LoginFailed => "login_failed",
+            AuditAction::TokenIssued => "token_issued",
+            AuditAction::TokenRevoked => "token_revoked",
+            AuditAction::DeviceRegistered => "device_registered",
+            AuditAction::DeviceBound => "device_bound",
+            AuditAction::DeviceUnbound => "device_unbound",
+            AuditAction::GroupShared => "group_shared",
+            AuditAction::GroupUnshared => "group_unshared",
+            AuditAction::SessionStarted => "session_started",
+            AuditAction::SessionEnded => "session_ended",
+            AuditAction::SessionResumed => "session_resumed",
+            AuditAction::AdminDisconnect => "admin_disconnect",
+            AuditAction::AdminBan => "admin_ban",
+            AuditAction::AdminUnban => "admin_unban",
+        }
+    }
+}
+
+impl TryFrom<&str> for AuditAction {
+    type Error = anyhow::Error;
+
+    fn try_from(action: &str) -> Result<Self> {
+        serde_json::from_value(Value::String(action.to_owned()))
+            .map_err(|_| anyhow!("Invalid audit action: {}", action))
+    }
+}
+
+// 审计日志，只追加不修改；actor为操作者（用户id或admin，匿名时为空），target为操作对象
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub action: AuditAction,
+    pub actor: String,
+    pub target: String,
+    pub ip: String,
+    pub user_agent: String,
+    // 失败原因等附加信息
+    #[serde(default)]
+    pub detail: String,
+    pub created_at: Timestamp,
+}
+
+impl AuditEntry {
+    pub fn with_detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = detail.into();
+        self
+    }
+}
+
+// 查询审计日志的条件，未设置的字段不过滤；时间范围为 [since, until)
+#[derive(Debug, Default)]
+pub struct AuditFilter {
+    pub action: Option<AuditAction>,
+    pub actor: Option<String>,
+    pub target: Option<String>,
+    pub since: Option<Timestamp>,
+    pub until: Option<Timestamp>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum RequestMethod {
+    Hello,
     GetNonce,
+    RegisterDevice,
+    Login,
+    Resume,
+    BindDevice,
+    UnbindDevice,
+    GetDeviceList,
+    SetDeviceTags,
+    CreateGroup,
+    UpdateGroup,
+    DeleteGroup,
+    GetGroupList,
+    AddGroupDevices,
+    RemoveGroupDevices,
+    ShareGroup,
+    UnshareGroup,
+    SubscribePresence,
+    UnsubscribePresence,
+}
+
+impl RequestMethod {
+    pub const ALL: [RequestMethod; 19] = [
+        Self::Hello,
+        Self::GetNonce,
+        Self::RegisterDevice,
+        Self::Login,
+        Self::Resume,
+        Self::BindDevice,
+        Self::UnbindDevice,
+        Self::GetDeviceList,
+        Self::SetDeviceTags,
+        Self::CreateGroup,
+        Self::UpdateGroup,
+        Self::DeleteGroup,
+        Self::GetGroupList,
+        Self::AddGroupDevices,
+        Self::RemoveGroupDevices,
+        Self::ShareGroup,
+        Self::UnshareGroup,
+        Self::SubscribePresence,
+        Self::UnsubscribePresence,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Hello => "hello",
+            Self::GetNonce => "getNonce",
+            Self::RegisterDevice => "registerDevice",
+            Self::Login => "login",
+            Self::Resume => "resume",
+            Self::BindDevice => "bindDevice",
+            Self::UnbindDevice => "unbindDevice",
+            Self::GetDeviceList => "getDeviceList",
+            Self::SetDeviceTags => "setDeviceTags",
+            Self::CreateGroup => "createGroup",
+            Self::UpdateGroup => "updateGroup",
+            Self::DeleteGroup => "deleteGroup",
+            Self::GetGroupList => "getGroupList",
+            Self::AddGroupDevices => "addGroupDevices",
+            Self::RemoveGroupDevices => "removeGroupDevices",
+            Self::ShareGroup => "shareGroup",
+            Self::UnshareGroup => "unshareGroup",
+            Self::SubscribePresence => "subscribePresence",
+            Self::UnsubscribePresence => "unsubscribePresence",
+        }
+    }
 }
 
 impl TryFrom<&str> for RequestMethod {
     type Error = anyhow::Error;
     fn try_from(method: &str) -> Result<Self, Self::Error> {
-        let err = || anyhow!(format!("Method not found: {}", method));
-
-        if method == "getNonce" {
-            return Ok(Self::GetNonce);
-        } else {
-            return Err(err());
-        }
+        Self::ALL
+            .into_iter()
+            .find(|m| m.as_str() == method)
+            .ok_or_else(|| anyhow!(format!("Method not found: {}", method)))
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+// 连接建立后可选的握手，client_version为客户端自身的版本号，如 "1.4.2"
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct HelloParams {
+    pub protocol_version: u32,
+    #[serde(default)]
+    pub client_version: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct RegisterDeviceParams {
     pub device_name: String,
     pub mac: String,
+    // 硬件指纹，与mac一起用于识别重复注册的设备
+    #[serde(default)]
+    pub fingerprint: String,
+    // 为true、携带了有效token且指纹非空时，若该用户已绑定相同mac和指纹的设备，则沿用原device_id
+    #[serde(default)]
+    pub reclaim: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct LoginParams {
     pub user_id: String,
     pub device_id: String,
@@ -55,23 +375,167 @@ pub struct LoginParams {
     pub signature: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+// 断线重连后恢复会话，last_seq为客户端收到的最后一条推送的seq，之后的推送会被补发
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct ResumeParams {
+    pub resume_token: String,
+    #[serde(default)]
+    pub last_seq: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct BindDeviceParams {
+    pub device_id: String,
+    pub device_name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct DeviceIdParams {
+    pub device_id: String,
+}
+
+// group_id 与 tag 均为可选过滤条件
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct GetDeviceListParams {
+    #[serde(default)]
+    pub group_id: Option<String>,
+    #[serde(default)]
+    pub tag: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SetDeviceTagsParams {
+    pub device_id: String,
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct CreateGroupParams {
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct UpdateGroupParams {
+    pub group_id: String,
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct GroupIdParams {
+    pub group_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct GroupDevicesParams {
+    pub group_id: String,
+    pub device_ids: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct ShareGroupParams {
+    pub group_id: String,
+    pub user_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct RegisterDeviceResult {
     pub device_id: String,
+    pub reclaimed: bool,
+    // 注册了新设备，但该用户已绑定相同mac和指纹的设备时为该设备的id
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duplicate_of: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct HelloResult {
+    pub server_version: String,
+    pub protocol_version: u32,
+    pub min_protocol_version: u32,
+    pub methods: Vec<String>,
+    pub features: Vec<String>,
+    // 连接已认证（升级时或login后）时为该连接会话的resume_token；服务端关闭会话恢复时没有
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resume_token: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct UserNonceResult {
     pub nonce: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct LoginResult {
     pub token: String,
+    // 用于断线重连后恢复会话，服务端关闭会话恢复时没有
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resume_token: Option<String>,
+}
+
+// gap为true时last_seq之后的部分推送已不在缓存中，客户端应重新获取设备列表等状态
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct ResumeResult {
+    pub token: String,
+    pub device_id: String,
+    pub replayed: usize,
+    pub gap: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct MessageResult {
+    pub message: String,
+}
+
+impl MessageResult {
+    pub fn ok() -> Self {
+        MessageResult { message: "Ok".to_owned() }
+    }
+}
+
+// 设备列表中的设备，tags为当前用户在自己的绑定上设置的标签，分享来的设备没有
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct DeviceListItem {
+    #[serde(flatten)]
+    pub device: DeviceInfo,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct DeviceListResult {
+    pub device_list: Vec<DeviceListItem>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct GroupResult {
+    pub group: DeviceGroup,
+}
+
+// owned: 自己创建的分组; shared: 他人分享给自己的分组
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct GroupListResult {
+    pub owned: Vec<DeviceGroup>,
+    pub shared: Vec<DeviceGroup>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PresenceChange {
+    Online,
+    Offline,
+    // 设备名称、标签等信息有变化，客户端需重新获取设备列表
+    Updated,
+}
+
+// subscribePresence后推送的设备状态变化
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct PresenceEvent {
+    pub device_id: String,
+    pub event: PresenceChange,
 }
 
 // 服务端返回的数据类型
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[schemars(rename = "ResponseParams")]
 pub struct ResponseParams<T: Debug + Serialize + Serialize> {
     pub id: u64,
     pub method: String,
@@ -79,7 +543,7 @@ pub struct ResponseParams<T: Debug + Serialize + Serialize> {
     pub result: T,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct RequestParams {
     pub id: u64,
     pub method: String,
@@ -1,21 +1,122 @@
 use anyhow::{anyhow, Error, Result};
 use sp_core::{crypto::Ss58Codec, sr25519, sr25519::Signature};
 use sp_runtime::traits::Verify;
-use std::time::Instant;
-
-pub fn now() -> String {
-    let now = Instant::now();
-    let elapsed = now.elapsed();
-    let elapsed_secs = elapsed.as_secs();
-    let elapsed_nanos = elapsed.subsec_nanos();
-    format!("{}.{:09}", elapsed_secs, elapsed_nanos)
+
+use crate::types::Timestamp;
+
+pub fn now() -> Timestamp {
+    Timestamp::now()
+}
+
+// 将MAC地址统一为 "00:2B:67:6F:74:72" 的格式
+// 支持 ":" 或 "-" 分隔的6组、"." 分隔的3组（0011.2233.4455）或无分隔的写法，拒绝全0及广播地址
+pub fn normalize_mac(mac: &str) -> Result<String, Error> {
+    let invalid = || anyhow!("invalid mac address: {}", mac);
+    let trimmed = mac.trim();
+    let (groups, group_len): (Vec<&str>, usize) = match trimmed.find([':', '-', '.']) {
+        None => (vec![trimmed], 12),
+        Some(i) => {
+            let sep = trimmed.as_bytes()[i] as char;
+            let groups = trimmed.split(sep).collect();
+            (groups, if sep == '.' { 4 } else { 2 })
+        }
+    };
+    if groups.len() != 12 / group_len
+        || groups
+            .iter()
+            .any(|g| g.len() != group_len || !g.chars().all(|c| c.is_ascii_hexdigit()))
+    {
+        return Err(invalid());
+    }
+    let digits = groups.concat().to_ascii_uppercase();
+    if digits == "000000000000" || digits == "FFFFFFFFFFFF" {
+        return Err(invalid());
+    }
+    let octets: Vec<&str> = (0..12).step_by(2).map(|i| &digits[i..i + 2]).collect();
+    Ok(octets.join(":"))
+}
+
+// 解析 "1.4.2" 格式的版本号，缺少的部分视为0，忽略 "-beta.1" 等预发布后缀
+pub fn parse_version(version: &str) -> Option<(u64, u64, u64)> {
+    let version = version.trim().trim_start_matches('v');
+    let core = version.split(['-', '+']).next()?;
+    let mut parts = core.split('.').map(|p| p.parse::<u64>().ok());
+    let major = parts.next()??;
+    let minor = parts.next().unwrap_or(Some(0))?;
+    let patch = parts.next().unwrap_or(Some(0))?;
+    if parts.next().is_some() {
+        return None;
+    }
+    Some((major, minor, patch))
 }
 
 pub fn verify_signature(addr: &str, msg: &str, sig: &str) -> Result<bool, Error> {
-    let msg: &[u8] = &msg.as_bytes().to_vec();
+    let msg: &[u8] = msg.as_bytes();
     let sig: &[u8] = &hex::decode(sig.trim_start_matches("0x"))?;
 
     let pubkey = <sr25519::Public as Ss58Codec>::from_ss58check(addr)?;
     let sig = Signature::try_from(sig).map_err(|e| anyhow!("{:?}", e))?;
     Ok(sig.verify(msg, &pubkey))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_version_fills_missing_parts() {
+        assert_eq!(parse_version("1.4.2"), Some((1, 4, 2)));
+        assert_eq!(parse_version(" v2.1 "), Some((2, 1, 0)));
+        assert_eq!(parse_version("3"), Some((3, 0, 0)));
+        assert_eq!(parse_version("1.2.3-beta.1+build.5"), Some((1, 2, 3)));
+    }
+
+    #[test]
+    fn parse_version_rejects_garbage() {
+        for version in ["", "v", "1.x", "1.2.3.4", "1..2", "-1.0", "abc"] {
+            assert_eq!(parse_version(version), None, "{}", version);
+        }
+    }
+
+    #[test]
+    fn versions_compare_numerically() {
+        assert!(parse_version("1.10.0") > parse_version("1.9.9"));
+        assert!(parse_version("2.0") > parse_version("1.99.99"));
+    }
+
+    #[test]
+    fn normalize_mac_accepts_common_formats() {
+        for mac in [
+            "00:2b:67:6f:74:72",
+            "00-2B-67-6F-74-72",
+            "002B.676F.7472",
+            "002b676f7472",
+            " 00:2B:67:6F:74:72 ",
+        ] {
+            assert_eq!(normalize_mac(mac).unwrap(), "00:2B:67:6F:74:72", "{}", mac);
+        }
+    }
+
+    #[test]
+    fn normalize_mac_rejects_bad_grouping() {
+        for mac in [
+            "00112:2334455",
+            "00:11:22:33:4455",
+            "00:11-22:33:44:55",
+            "0011.2233.44.55",
+            "00:11:22:33:44:55:66",
+            "00:11:22:33:44",
+            "0:11:22:33:44:555",
+            "",
+        ] {
+            assert!(normalize_mac(mac).is_err(), "{}", mac);
+        }
+    }
+
+    #[test]
+    fn normalize_mac_rejects_non_hex_and_reserved() {
+        for mac in ["00:11:22:33:44:GG", "00:00:00:00:00:00", "ff:ff:ff:ff:ff:ff"] {
+            assert!(normalize_mac(mac).is_err(), "{}", mac);
+        }
+    }
+}